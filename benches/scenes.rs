@@ -0,0 +1,108 @@
+extern crate twoxel;
+
+use std::env;
+use std::time::{Duration, Instant};
+
+use twoxel::simulation;
+use twoxel::simulation::Simulation;
+
+// Steps a set of stress scenes and reports the time per step together with the
+// work the broadphase and the solver did. Run all of them with
+// cargo bench --bench scenes, or some of them by name with
+// cargo bench --bench scenes -- pyramid chain [--steps N].
+struct Scene {
+    name: &'static str,
+    build: fn() -> Simulation,
+    num_steps: usize
+}
+
+const SCENES: &[Scene] = &[
+    Scene { name: "pyramid", build: pyramid, num_steps: 300 },
+    Scene { name: "stack", build: stack, num_steps: 300 },
+    // The top rows of the pile only land after about 600 steps and settling takes
+    // well over a thousand, at about half a second per step. The time per step is
+    // dominated by the broadphase, which tests all pairs of bodies whether they
+    // are falling or resting, so the first steps already measure it. Pass --steps
+    // to follow the pile further.
+    Scene { name: "polygon_pile", build: polygon_pile, num_steps: 50 },
+    Scene { name: "chain", build: chain, num_steps: 300 },
+    Scene { name: "circle_pile", build: circle_pile, num_steps: 300 }
+];
+
+fn pyramid() -> Simulation {
+    simulation::test_pyramid(20)
+}
+
+fn stack() -> Simulation {
+    simulation::test_stack(100)
+}
+
+fn polygon_pile() -> Simulation {
+    simulation::test_polygon_pile(0, 5000)
+}
+
+fn chain() -> Simulation {
+    simulation::test_chain(200)
+}
+
+fn circle_pile() -> Simulation {
+    simulation::test_circle_pile(1000)
+}
+
+struct Result {
+    step_times: Vec<Duration>,
+    num_pairs: usize,
+    num_collisions: usize,
+    num_constraint_solves: usize
+}
+
+fn run(sim: &mut Simulation, num_steps: usize) -> Result {
+    let mut result = Result { step_times: vec![], num_pairs: 0, num_collisions: 0, num_constraint_solves: 0 };
+    for _ in 0..num_steps {
+        let start = Instant::now();
+        sim.timestep();
+        result.step_times.push(start.elapsed());
+        result.num_pairs += sim.collision_handler.get_num_pairs();
+        result.num_collisions += sim.collision_handler.collisions.len();
+        result.num_constraint_solves += sim.collision_handler.get_num_constraint_solves();
+    }
+    result
+}
+
+fn milliseconds(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+fn main() {
+    let mut names = vec![];
+    let mut num_steps = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            // Passed by cargo bench.
+            "--bench" => {}
+            "--steps" => { num_steps = args.next().and_then(|value| value.parse().ok()) }
+            _ => { names.push(arg) }
+        }
+    }
+    println!("{:<14} {:>7} {:>7} {:>6} {:>10} {:>10} {:>10} {:>11} {:>10} {:>12}",
+             "scene", "bodies", "joints", "steps", "mean ms", "min ms", "max ms", "pairs/step", "contacts", "solves/step");
+    for scene in SCENES.iter().filter(|scene| names.is_empty() || names.iter().any(|name| name == scene.name)) {
+        let mut sim = (scene.build)();
+        let steps = num_steps.unwrap_or(scene.num_steps);
+        let result = run(&mut sim, steps);
+        let total: Duration = result.step_times.iter().sum();
+        let per_step = |count: usize| count as f64 / steps.max(1) as f64;
+        println!("{:<14} {:>7} {:>7} {:>6} {:>10.3} {:>10.3} {:>10.3} {:>11.1} {:>10.1} {:>12.1}",
+                 scene.name,
+                 sim.bodies.len(),
+                 sim.joints.len(),
+                 steps,
+                 milliseconds(total) / steps.max(1) as f64,
+                 milliseconds(result.step_times.iter().cloned().min().unwrap_or_default()),
+                 milliseconds(result.step_times.iter().cloned().max().unwrap_or_default()),
+                 per_step(result.num_pairs),
+                 per_step(result.num_collisions),
+                 per_step(result.num_constraint_solves));
+    }
+}
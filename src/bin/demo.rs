@@ -0,0 +1,196 @@
+extern crate piston_window;
+extern crate opengl_graphics;
+extern crate twoxel;
+
+use std::env;
+use std::process;
+
+use piston_window::{EventLoop, Input, OpenGL, PistonWindow, WindowSettings, Motion, MouseScrollEvent, Button, MouseButton};
+use opengl_graphics::GlGraphics;
+
+use twoxel::simulation;
+use twoxel::simulation::Simulation;
+use twoxel::simulation::body;
+use twoxel::simulation::aabb::Aabb;
+use twoxel::simulation::replay;
+use twoxel::simulation::replay::{Recorder, Replay, Player};
+use twoxel::simulation::scene::BodyDescription;
+use twoxel::point::Point;
+use twoxel::render::Renderer;
+
+// Drag bodies with the left mouse button and drop boxes with the right one. With
+// --record the run is written as a replay when the window is closed, with --replay
+// a recorded run is played back and checked step by step instead.
+const USAGE: &str = "usage: demo [scene] [--record PATH | --replay PATH]";
+
+enum Mode {
+    Interactive(Simulation, Option<Recorder>),
+    // The flag is set once the replay diverged, after which it stops.
+    Playback(Player, bool)
+}
+
+// A mouse joint on a body, see Simulation::drag_body.
+struct Drag {
+    body: usize,
+    anchor: Point
+}
+
+impl Mode {
+    fn get_sim(&mut self) -> &mut Simulation {
+        match *self {
+            Mode::Interactive(ref mut sim, _) => { sim }
+            Mode::Playback(ref mut player, _) => { &mut player.sim }
+        }
+    }
+
+    // Inputs of the user are recorded if needed and ignored during playback.
+    fn apply(&mut self, input: replay::Input) {
+        if let Mode::Interactive(ref mut sim, ref mut recorder) = *self {
+            let result = match *recorder {
+                Some(ref mut recorder) => { recorder.apply(sim, input) }
+                None => { input.apply(sim) }
+            };
+            if let Err(message) = result {
+                eprintln!("{}", message);
+            }
+        }
+    }
+
+    fn timestep(&mut self) {
+        match *self {
+            Mode::Interactive(ref mut sim, Some(ref mut recorder)) => { recorder.timestep(sim) }
+            Mode::Interactive(ref mut sim, None) => { sim.timestep() }
+            Mode::Playback(ref mut player, ref mut has_diverged) => {
+                if *has_diverged || player.is_finished() {
+                    return
+                }
+                if let Err(message) = player.timestep() {
+                    eprintln!("{}", message);
+                    *has_diverged = true;
+                }
+                else if player.is_finished() {
+                    println!("replay finished after {} steps without diverging", player.get_step());
+                }
+            }
+        }
+    }
+}
+
+fn main() {
+    let (scene, record, replay) = parse_args(env::args().skip(1).collect()).unwrap_or_else(|message| {
+        eprintln!("{}\n{}", message, USAGE);
+        process::exit(2);
+    });
+
+    let opengl = OpenGL::V3_2;
+
+    let mut window: PistonWindow = WindowSettings::new(
+        "Twoxel", [1024 as u32, 600 as u32])
+        .opengl(opengl).samples(8).exit_on_esc(true).build().unwrap();
+
+    window.set_ups(60);
+    window.set_max_fps(60);
+
+    let mut gl = GlGraphics::new(opengl);
+
+    let mut mode = match replay {
+        Some(path) => {
+            let replay = Replay::load(&path).unwrap_or_else(|err| panic!("could not load replay {}: {}", path, err));
+            Mode::Playback(Player::new(replay).unwrap_or_else(|err| panic!("could not load the scene of replay {}: {}", path, err)), false)
+        }
+        None => {
+            let sim = match scene {
+                Some(path) => {
+                    Simulation::from_scene_file(&path).unwrap_or_else(|err| panic!("could not load scene {}: {}", path, err))
+                }
+                None => { simulation::test_collision_4() }
+            };
+            let recorder = record.as_ref().map(|_| Recorder::new(&sim));
+            Mode::Interactive(sim, recorder)
+        }
+    };
+
+    let dimensions = window.output_color.get_dimensions();
+    let window_dimensions = Point{x: (dimensions.0 as f64), y: (dimensions.1 as f64)};
+    let mut renderer = Renderer::new(window_dimensions);
+    let mut cursor = Point::new(0.0, 0.0);
+    let mut drag: Option<Drag> = None;
+
+    while let Some(e) = window.next() {
+        match e {
+            Input::Update(_) => {
+                if let Some(ref drag) = drag {
+                    let target = renderer.to_world(cursor);
+                    mode.apply(replay::Input::MouseJoint { body: drag.body, anchor: drag.anchor, target });
+                }
+                mode.timestep();
+            }
+
+            Input::Render(args) => {
+                gl.draw(args.viewport(), |context, gl| renderer.render(context, gl, mode.get_sim()));
+            }
+
+            Input::Move(Motion::MouseCursor(x, y)) => {
+                cursor = Point::new(x, y);
+            }
+
+            Input::Press(Button::Mouse(MouseButton::Left)) => {
+                drag = pick_body(mode.get_sim(), renderer.to_world(cursor));
+            }
+
+            Input::Release(Button::Mouse(MouseButton::Left)) => {
+                drag = None;
+            }
+
+            Input::Press(Button::Mouse(MouseButton::Right)) => {
+                let body = body::get_rectangle(renderer.to_world(cursor), 1.0, 1.0, 1.0);
+                mode.apply(replay::Input::Spawn { body: BodyDescription::from_body(&body) });
+            }
+
+            _ => {}
+        }
+        e.mouse_scroll(|_dx, dy| renderer.scale_factor = zoom(dy, renderer.scale_factor));
+    }
+
+    if let (Some(path), Mode::Interactive(_, Some(recorder))) = (record, mode) {
+        let replay = recorder.finish();
+        replay.save(&path).unwrap_or_else(|err| panic!("could not write replay {}: {}", path, err));
+        println!("recorded {} steps to {}", replay.steps.len(), path);
+    }
+}
+
+fn zoom(dy: f64, scale_factor: f64) -> f64 {
+    scale_factor * (1.0 + dy / 10.0)
+}
+
+// The dynamic body whose bounding box contains the point and whose center is
+// closest to it. The anchor of the drag is the point on that body.
+fn pick_body(sim: &Simulation, point: Point) -> Option<Drag> {
+    let cursor = Aabb::from_points(&[point]);
+    sim.bodies.iter().enumerate()
+        .filter(|&(_, body)| !body.is_static && body.shape.get_aabb().overlaps(&cursor))
+        .min_by(|&(_, a), &(_, b)| (a.pos - point).norm().partial_cmp(&(b.pos - point).norm()).unwrap())
+        .map(|(index, body)| Drag { body: index, anchor: (point - body.pos).rotate(-body.apos) })
+}
+
+fn parse_args(args: Vec<String>) -> Result<(Option<String>, Option<String>, Option<String>), String> {
+    let (mut scene, mut record, mut replay) = (None, None, None);
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--record" => { record = Some(args.next().ok_or_else(|| "missing value for --record".to_string())?) }
+            "--replay" => { replay = Some(args.next().ok_or_else(|| "missing value for --replay".to_string())?) }
+            _ if arg.starts_with("--") => { return Err(format!("unknown option: {}", arg)) }
+            _ => {
+                if scene.is_some() {
+                    return Err(format!("unexpected argument: {}", arg));
+                }
+                scene = Some(arg);
+            }
+        }
+    }
+    if replay.is_some() && (scene.is_some() || record.is_some()) {
+        return Err("--replay cannot be used with a scene or --record".to_string())
+    }
+    Ok((scene, record, replay))
+}
@@ -0,0 +1,249 @@
+extern crate twoxel;
+
+use std::env;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::process;
+
+use twoxel::simulation;
+use twoxel::simulation::Simulation;
+use twoxel::simulation::replay::{Replay, Recorder, Player};
+
+// Runs a scene without a window and writes the state of every body after each step.
+// Exits with status 1 if an invariant breaks or a replay diverges and with status 2
+// on invalid arguments.
+const USAGE: &str = "usage: headless <scene> [--steps N] [--format csv|jsonl] [--output PATH] [--max-penetration DEPTH] [--seed SEED] [--record PATH]
+       headless --replay PATH [--steps N] [--format csv|jsonl] [--output PATH] [--max-penetration DEPTH]
+scene: a scene file or one of collision_1, collision_2, collision_3, collision_4, random_polygons
+--record writes a replay of the run, --replay plays one back and checks every step against it";
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Csv,
+    JsonLines
+}
+
+struct Options {
+    scene: Option<String>,
+    replay: Option<String>,
+    record: Option<String>,
+    steps: Option<usize>,
+    format: Format,
+    output: Option<String>,
+    max_penetration: f64,
+    seed: u64
+}
+
+fn main() {
+    let options = parse_args(env::args().skip(1).collect()).unwrap_or_else(|message| {
+        eprintln!("{}\n{}", message, USAGE);
+        process::exit(2);
+    });
+    let mut runner = get_runner(&options).unwrap_or_else(|message| {
+        eprintln!("{}", message);
+        process::exit(2);
+    });
+    let result = match options.output {
+        Some(ref path) => {
+            let file = File::create(path).unwrap_or_else(|err| {
+                eprintln!("could not create {}: {}", path, err);
+                process::exit(2);
+            });
+            run(&mut runner, &options, &mut BufWriter::new(file))
+        }
+        None => {
+            let stdout = io::stdout();
+            let mut out = BufWriter::new(stdout.lock());
+            run(&mut runner, &options, &mut out)
+        }
+    };
+    if let (Some(ref path), Runner::Scene(_, Some(recorder))) = (&options.record, runner) {
+        recorder.finish().save(path).unwrap_or_else(|err| {
+            eprintln!("could not write replay {}: {}", path, err);
+            process::exit(2);
+        });
+    }
+    match result {
+        Ok(Ok(())) => {}
+        Ok(Err(violation)) => {
+            eprintln!("{}", violation);
+            process::exit(1);
+        }
+        Err(err) => {
+            eprintln!("could not write output: {}", err);
+            process::exit(2);
+        }
+    }
+}
+
+// Steps a scene, recording it if a replay is written, or plays back a replay.
+enum Runner {
+    Scene(Simulation, Option<Recorder>),
+    Replay(Player)
+}
+
+impl Runner {
+    fn timestep(&mut self) -> Result<(), String> {
+        match *self {
+            Runner::Scene(ref mut sim, Some(ref mut recorder)) => { recorder.timestep(sim) }
+            Runner::Scene(ref mut sim, None) => { sim.timestep() }
+            Runner::Replay(ref mut player) => { return player.timestep() }
+        }
+        Ok(())
+    }
+
+    fn get_sim(&self) -> &Simulation {
+        match *self {
+            Runner::Scene(ref sim, _) => { sim }
+            Runner::Replay(ref player) => { &player.sim }
+        }
+    }
+
+    fn num_steps(&self) -> usize {
+        match *self {
+            Runner::Scene(_, _) => { 1000 }
+            Runner::Replay(ref player) => { player.num_steps() }
+        }
+    }
+}
+
+fn run<W: Write>(runner: &mut Runner, options: &Options, out: &mut W) -> io::Result<Result<(), String>> {
+    if options.format == Format::Csv {
+        writeln!(out, "step,body,x,y,angle,vx,vy,avel")?;
+    }
+    let steps = options.steps.unwrap_or_else(|| runner.num_steps());
+    for step in 1..steps + 1 {
+        if let Err(message) = runner.timestep() {
+            out.flush()?;
+            return Ok(Err(message));
+        }
+        let sim = runner.get_sim();
+        if let Err(violation) = check_invariants(sim, options.max_penetration) {
+            out.flush()?;
+            return Ok(Err(format!("step {}: {}", step, violation)));
+        }
+        write_step(sim, step, options.format, out)?;
+    }
+    out.flush()?;
+    Ok(Ok(()))
+}
+
+fn write_step<W: Write>(sim: &Simulation, step: usize, format: Format, out: &mut W) -> io::Result<()> {
+    for (i, body) in sim.bodies.iter().enumerate() {
+        match format {
+            Format::Csv => {
+                writeln!(out, "{},{},{},{},{},{},{},{}", step, i, body.pos.x, body.pos.y, body.apos, body.vel.x, body.vel.y, body.avel)?;
+            }
+            Format::JsonLines => {
+                writeln!(out, "{{\"step\":{},\"body\":{},\"x\":{},\"y\":{},\"angle\":{},\"vx\":{},\"vy\":{},\"avel\":{}}}", step, i, body.pos.x, body.pos.y, body.apos, body.vel.x, body.vel.y, body.avel)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn check_invariants(sim: &Simulation, max_penetration: f64) -> Result<(), String> {
+    for (i, body) in sim.bodies.iter().enumerate() {
+        let values = [body.pos.x, body.pos.y, body.apos, body.vel.x, body.vel.y, body.avel];
+        if values.iter().any(|value| !value.is_finite()) {
+            return Err(format!("body {} has a non-finite state (pos {:?}, angle {}, vel {:?}, avel {})", i, body.pos, body.apos, body.vel, body.avel));
+        }
+    }
+    for collision in sim.collision_handler.collisions.iter() {
+        if collision.info.depth > max_penetration {
+            return Err(format!("bodies {} and {} penetrate by {} (maximum {})", collision.body1, collision.body2, collision.info.depth, max_penetration));
+        }
+    }
+    Ok(())
+}
+
+fn get_runner(options: &Options) -> Result<Runner, String> {
+    match (options.scene.as_ref(), options.replay.as_ref()) {
+        (Some(scene), None) => {
+            let sim = get_scene(scene, options.seed).map_err(|err| format!("could not load scene {}: {}", scene, err))?;
+            let recorder = options.record.as_ref().map(|_| Recorder::new(&sim));
+            Ok(Runner::Scene(sim, recorder))
+        }
+        (None, Some(path)) => {
+            let replay = Replay::load(path).map_err(|err| format!("could not load replay {}: {}", path, err))?;
+            if options.steps.is_some_and(|steps| steps > replay.steps.len()) {
+                return Err(format!("the replay only has {} steps", replay.steps.len()))
+            }
+            Ok(Runner::Replay(Player::new(replay).map_err(|err| format!("could not load the scene of replay {}: {}", path, err))?))
+        }
+        _ => { unreachable!() }
+    }
+}
+
+fn get_scene(name: &str, seed: u64) -> io::Result<Simulation> {
+    match name {
+        "collision_1" => { Ok(simulation::test_collision_1()) }
+        "collision_2" => { Ok(simulation::test_collision_2()) }
+        "collision_3" => { Ok(simulation::test_collision_3()) }
+        "collision_4" => { Ok(simulation::test_collision_4()) }
+        "random_polygons" => { Ok(simulation::test_random_polygons(seed, 30)) }
+        path => { Simulation::from_scene_file(path) }
+    }
+}
+
+fn parse_args(args: Vec<String>) -> Result<Options, String> {
+    let mut scene = None;
+    let mut options = Options {
+        scene: None,
+        replay: None,
+        record: None,
+        steps: None,
+        format: Format::Csv,
+        output: None,
+        max_penetration: 0.5,
+        seed: 0
+    };
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or_else(|| format!("missing value for {}", name));
+        match arg.as_str() {
+            "--steps" => {
+                options.steps = Some(value("--steps")?.parse().map_err(|_| "--steps needs a non-negative integer".to_string())?);
+            }
+            "--format" => {
+                options.format = match value("--format")?.as_str() {
+                    "csv" => { Format::Csv }
+                    "jsonl" => { Format::JsonLines }
+                    other => { return Err(format!("unknown format: {}", other)) }
+                };
+            }
+            "--output" => {
+                options.output = Some(value("--output")?);
+            }
+            "--record" => {
+                options.record = Some(value("--record")?);
+            }
+            "--replay" => {
+                options.replay = Some(value("--replay")?);
+            }
+            "--seed" => {
+                options.seed = value("--seed")?.parse().map_err(|_| "--seed needs a non-negative integer".to_string())?;
+            }
+            "--max-penetration" => {
+                options.max_penetration = value("--max-penetration")?.parse().map_err(|_| "--max-penetration needs a number".to_string())?;
+            }
+            _ if arg.starts_with("--") => { return Err(format!("unknown option: {}", arg)) }
+            _ => {
+                if scene.is_some() {
+                    return Err(format!("unexpected argument: {}", arg));
+                }
+                scene = Some(arg);
+            }
+        }
+    }
+    options.scene = scene;
+    match (&options.scene, &options.replay) {
+        (&None, &None) => { return Err("missing scene".to_string()) }
+        (&Some(_), &Some(_)) => { return Err("a replay already contains its scene".to_string()) }
+        _ => {}
+    }
+    if options.replay.is_some() && options.record.is_some() {
+        return Err("--record cannot be used with --replay".to_string())
+    }
+    Ok(options)
+}
@@ -0,0 +1,247 @@
+use std::cmp;
+use std::fmt;
+use std::ops::{Add, Sub, Mul, Div, Neg, AddAssign, SubAssign, MulAssign, DivAssign};
+
+use point::Point;
+use scalar::Scalar;
+
+const FRACTION_BITS: u32 = 32;
+// Pi, 2 pi and pi / 2 in the raw representation.
+const PI: i64 = 13_493_037_705;
+const TWO_PI: i64 = 26_986_075_409;
+const HALF_PI: i64 = 6_746_518_852;
+// sin and cos are evaluated with this many fraction bits.
+const SERIES_BITS: u32 = 61;
+
+// A Q32.32 fixed point number. All operations are integer operations, so they
+// give the same results on every CPU. Results outside of the range saturate,
+// and dividing by zero gives the largest value with the sign of the dividend.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Fixed(i64);
+
+impl Fixed {
+    pub fn from_raw(raw: i64) -> Fixed {
+        Fixed(raw)
+    }
+
+    pub fn raw(self) -> i64 {
+        self.0
+    }
+
+    fn saturate(value: i128) -> Fixed {
+        Fixed(cmp::max(cmp::min(value, i64::MAX as i128), i64::MIN as i128) as i64)
+    }
+}
+
+impl Scalar for Fixed {
+    fn from_f64(value: f64) -> Fixed {
+        // Casting a float to an integer saturates.
+        Fixed((value * (1u64 << FRACTION_BITS) as f64).round() as i64)
+    }
+
+    fn to_f64(self) -> f64 {
+        self.0 as f64 / (1u64 << FRACTION_BITS) as f64
+    }
+
+    fn to_bits(self) -> u64 {
+        self.0 as u64
+    }
+
+    fn max_value() -> Fixed {
+        Fixed(i64::MAX)
+    }
+
+    fn min_value() -> Fixed {
+        Fixed(i64::MIN)
+    }
+
+    // The square root of negative numbers is zero. Rounds to the nearest value.
+    fn sqrt(self) -> Fixed {
+        if self.0 <= 0 {
+            return Fixed(0)
+        }
+        let value = (self.0 as u128) << FRACTION_BITS;
+        let root = integer_sqrt(value);
+        // (root + 1/2)^2 = root^2 + root + 1/4
+        Fixed(if value - root * root > root { root + 1 } else { root } as i64)
+    }
+
+    fn sin(self) -> Fixed {
+        Fixed(sin_raw(self.0.rem_euclid(TWO_PI)))
+    }
+
+    fn cos(self) -> Fixed {
+        Fixed(sin_raw(self.0.rem_euclid(TWO_PI) + HALF_PI))
+    }
+
+    fn is_finite(self) -> bool {
+        true
+    }
+}
+
+// The sine of an angle in [0, 2 pi + pi / 2) in the raw representation.
+fn sin_raw(angle: i64) -> i64 {
+    // Move the angle to [-pi / 2, pi / 2] with sin(x) = sin(x - 2 pi) = sin(pi - x).
+    let mut x = angle;
+    if x > PI {
+        x -= TWO_PI;
+    }
+    if x > HALF_PI {
+        x = PI - x;
+    }
+    else if x < -HALF_PI {
+        x = -PI - x;
+    }
+    // Taylor series up to x^17 in the nested form
+    // x (1 - x^2 / (2 3) (1 - x^2 / (4 5) (1 - ...))).
+    let one = 1i128 << SERIES_BITS;
+    let x = (x as i128) << (SERIES_BITS - FRACTION_BITS);
+    let x_sqr = (x * x) >> SERIES_BITS;
+    let mut series = one;
+    for n in (1..9).rev() {
+        series = one - ((x_sqr * series) >> SERIES_BITS) / (2 * n * (2 * n + 1));
+    }
+    let sin = (x * series) >> SERIES_BITS;
+    ((sin + (1 << (SERIES_BITS - FRACTION_BITS - 1))) >> (SERIES_BITS - FRACTION_BITS)) as i64
+}
+
+// The largest integer whose square is at most value.
+fn integer_sqrt(value: u128) -> u128 {
+    let mut remainder = value;
+    let mut result = 0;
+    let mut bit = 1u128 << 126;
+    while bit > remainder {
+        bit >>= 2;
+    }
+    while bit != 0 {
+        if remainder >= result + bit {
+            remainder -= result + bit;
+            result = (result >> 1) + bit;
+        }
+        else {
+            result >>= 1;
+        }
+        bit >>= 2;
+    }
+    result
+}
+
+impl fmt::Debug for Fixed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_f64())
+    }
+}
+
+impl fmt::Display for Fixed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_f64())
+    }
+}
+
+impl Add for Fixed {
+    type Output = Fixed;
+    fn add(self, other: Fixed) -> Fixed {
+        Fixed(self.0.saturating_add(other.0))
+    }
+}
+
+impl Sub for Fixed {
+    type Output = Fixed;
+    fn sub(self, other: Fixed) -> Fixed {
+        Fixed(self.0.saturating_sub(other.0))
+    }
+}
+
+impl Mul for Fixed {
+    type Output = Fixed;
+    fn mul(self, other: Fixed) -> Fixed {
+        // Round to the nearest value.
+        let product = self.0 as i128 * other.0 as i128;
+        Fixed::saturate((product + (1 << (FRACTION_BITS - 1))) >> FRACTION_BITS)
+    }
+}
+
+impl Div for Fixed {
+    type Output = Fixed;
+    fn div(self, other: Fixed) -> Fixed {
+        if other.0 == 0 {
+            return match self.0.cmp(&0) {
+                cmp::Ordering::Greater => { Fixed(i64::MAX) }
+                cmp::Ordering::Less => { Fixed(i64::MIN) }
+                cmp::Ordering::Equal => { Fixed(0) }
+            }
+        }
+        Fixed::saturate(((self.0 as i128) << FRACTION_BITS) / other.0 as i128)
+    }
+}
+
+impl Neg for Fixed {
+    type Output = Fixed;
+    fn neg(self) -> Fixed {
+        Fixed(self.0.saturating_neg())
+    }
+}
+
+impl AddAssign for Fixed {
+    fn add_assign(&mut self, other: Fixed) {
+        *self = *self + other;
+    }
+}
+
+impl SubAssign for Fixed {
+    fn sub_assign(&mut self, other: Fixed) {
+        *self = *self - other;
+    }
+}
+
+impl MulAssign for Fixed {
+    fn mul_assign(&mut self, other: Fixed) {
+        *self = *self * other;
+    }
+}
+
+impl DivAssign for Fixed {
+    fn div_assign(&mut self, other: Fixed) {
+        *self = *self / other;
+    }
+}
+
+impl Mul<Point<Fixed>> for Fixed {
+    type Output = Point<Fixed>;
+    fn mul(self, other: Point<Fixed>) -> Point<Fixed> {
+        other * self
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use std::f64;
+
+    fn fixed(value: f64) -> Fixed {
+        Fixed::from_f64(value)
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        assert_eq!(fixed(1.5) + fixed(2.25), fixed(3.75));
+        assert_eq!(fixed(1.5) * fixed(-2.25), fixed(-3.375));
+        assert_eq!(fixed(1.0) / fixed(4.0), fixed(0.25));
+        assert_eq!(fixed(1.0) / fixed(0.0), Fixed::max_value());
+        assert_eq!(Fixed::max_value() + fixed(1.0), Fixed::max_value());
+        assert_eq!(fixed(2.0).sqrt(), fixed(f64::consts::SQRT_2));
+        assert_eq!(fixed(16.0).sqrt(), fixed(4.0));
+        assert_eq!(fixed(-1.0).sqrt(), fixed(0.0));
+    }
+
+    #[test]
+    fn test_trigonometry() {
+        for i in -100..100 {
+            let angle = i as f64 * 0.123;
+            assert!((fixed(angle).sin().to_f64() - angle.sin()).abs() < 1e-9, "sin({})", angle);
+            assert!((fixed(angle).cos().to_f64() - angle.cos()).abs() < 1e-9, "cos({})", angle);
+        }
+        assert_eq!(fixed(0.0).sin(), fixed(0.0));
+        assert_eq!(fixed(0.0).cos(), fixed(1.0));
+    }
+}
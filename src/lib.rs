@@ -0,0 +1,14 @@
+#[cfg(feature = "render")]
+extern crate piston_window;
+#[cfg(feature = "render")]
+extern crate opengl_graphics;
+
+extern crate serde;
+extern crate serde_json;
+
+pub mod point;
+pub mod scalar;
+pub mod fixed;
+pub mod simulation;
+#[cfg(feature = "render")]
+pub mod render;
@@ -5,39 +5,55 @@ use std::ops::Div;
 use std::ops::Neg;
 use std::ops::AddAssign;
 
+use serde::{Serialize, Deserialize};
+
+use scalar::Scalar;
+
 #[derive(Copy, Clone)]
-#[derive(Debug)]
-pub struct Point {
-    pub x : f64,
-    pub y : f64
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Point<S = f64> {
+    pub x : S,
+    pub y : S
 }
 
-impl Point {
-    pub fn norm(self) -> f64 {
+impl<S: Scalar> Point<S> {
+    pub fn norm(self) -> S {
         (self.x.powi(2) + self.y.powi(2)).sqrt()
     }
 
-    pub fn orth(self) -> Point {
+    pub fn orth(self) -> Point<S> {
         Point{x : -self.y, 
               y: self.x}
     }
     
-    pub fn middle(self, p: Point) -> Point {
-        return Point{
-            x: (self.x + p.x) * 0.5,
-            y: (self.y + p.y) * 0.5
+    pub fn middle(self, p: Point<S>) -> Point<S> {
+        Point{
+            x: (self.x + p.x) * S::from_f64(0.5),
+            y: (self.y + p.y) * S::from_f64(0.5)
         }
     }
 
-    pub fn normalized(self) -> Point {
-        return self / self.norm();
+    pub fn normalized(self) -> Point<S> {
+        self / self.norm()
     }
     
-    pub fn new(x:f64, y:f64) -> Point {
-        Point { x:x, y:y }
+    pub fn new(x:S, y:S) -> Point<S> {
+        Point { x, y }
+    }
+
+    pub fn zero() -> Point<S> {
+        Point { x: S::zero(), y: S::zero() }
     }
 
-    pub fn rotate(self, angle: f64) -> Point {
+    pub fn from_f64(point: Point<f64>) -> Point<S> {
+        Point { x: S::from_f64(point.x), y: S::from_f64(point.y) }
+    }
+
+    pub fn to_f64(self) -> Point<f64> {
+        Point { x: self.x.to_f64(), y: self.y.to_f64() }
+    }
+
+    pub fn rotate(self, angle: S) -> Point<S> {
         Point { 
             x: self.x * angle.cos() - self.y * angle.sin(),
             y: self.x * angle.sin() + self.y * angle.cos(),
@@ -45,9 +61,9 @@ impl Point {
     }
 }
 
-impl Add for Point {
-    type Output = Point;
-    fn add(self, other: Point) -> Point {
+impl<S: Scalar> Add for Point<S> {
+    type Output = Point<S>;
+    fn add(self, other: Point<S>) -> Point<S> {
         Point {
             x: self.x + other.x,
             y: self.y + other.y
@@ -55,9 +71,9 @@ impl Add for Point {
     }
 }
 
-impl Sub for Point {
-    type Output = Point;
-    fn sub(self, other: Point) -> Point {
+impl<S: Scalar> Sub for Point<S> {
+    type Output = Point<S>;
+    fn sub(self, other: Point<S>) -> Point<S> {
         Point {
             x: self.x - other.x,
             y: self.y - other.y
@@ -65,9 +81,9 @@ impl Sub for Point {
     }
 }
 
-impl Mul<f64> for Point {
-    type Output = Point;
-    fn mul(self, other: f64) -> Point {
+impl<S: Scalar> Mul<S> for Point<S> {
+    type Output = Point<S>;
+    fn mul(self, other: S) -> Point<S> {
         Point {
             x: self.x * other,
             y: self.y * other
@@ -85,16 +101,16 @@ impl Mul<Point> for f64 {
     }
 }
 
-impl Mul<Point> for Point {
-    type Output = f64;
-    fn mul(self, other: Point) -> f64 {
+impl<S: Scalar> Mul<Point<S>> for Point<S> {
+    type Output = S;
+    fn mul(self, other: Point<S>) -> S {
         self.x * other.x + self.y * other.y
     }
 }
 
-impl Div<f64> for Point {
-    type Output = Point;
-    fn div(self, other: f64) -> Point {
+impl<S: Scalar> Div<S> for Point<S> {
+    type Output = Point<S>;
+    fn div(self, other: S) -> Point<S> {
         Point {
             x: self.x / other,
             y: self.y / other
@@ -102,9 +118,9 @@ impl Div<f64> for Point {
     }
 }
 
-impl Neg for Point {
-    type Output = Point;
-    fn neg(self) -> Point {
+impl<S: Scalar> Neg for Point<S> {
+    type Output = Point<S>;
+    fn neg(self) -> Point<S> {
         Point {
             x: -self.x,
             y: -self.y
@@ -112,8 +128,8 @@ impl Neg for Point {
     }
 }
 
-impl AddAssign for Point {
-    fn add_assign(&mut self, other: Point) {
+impl<S: Scalar> AddAssign for Point<S> {
+    fn add_assign(&mut self, other: Point<S>) {
         self.x += other.x;
         self.y += other.y;
     }
@@ -6,10 +6,11 @@ use piston_window::types::Color;
 use opengl_graphics::GlGraphics;
 
 use ::point::Point;
+use std::f64;
 
 pub fn circle(pos: Point, radius: f64, color: Color, context: Context, gl: &mut GlGraphics) {
     Ellipse {
-            color: color,
+            color,
             border: None,
             resolution: 128,
     }.draw(
@@ -32,3 +33,50 @@ pub fn polygon(vertices: &Vec<Point>, color: Color, context: Context, gl: &mut G
         line(x, y, color, context, gl);
     }
 }
+
+pub fn polyline(vertices: &Vec<Point>, color: Color, context: Context, gl: &mut GlGraphics) {
+    for (x, y) in vertices.iter().zip(vertices[1..].iter()) {
+        line(x, y, color, context, gl);
+    }
+}
+
+pub fn capsule(start: Point, end: Point, radius: f64, color: Color, context: Context, gl: &mut GlGraphics) {
+    circle(start, radius, color, context, gl);
+    circle(end, radius, color, context, gl);
+    if start != end {
+        let offset = (end - start).normalized().orth() * radius;
+        line(&(start + offset), &(end + offset), color, context, gl);
+        line(&(start - offset), &(end - offset), color, context, gl);
+    }
+}
+
+pub fn rounded_polygon(vertices: &Vec<Point>, radius: f64, color: Color, context: Context, gl: &mut GlGraphics) {
+    // Draw the edges moved outwards by the radius and an arc around each vertex.
+    let num_vertices = vertices.len();
+    let signed_area: f64 = (0..num_vertices).map(|i| vertices[i].orth() * vertices[(i + 1) % num_vertices]).sum();
+    let outward_normal = |i: usize| {
+        let direction = (vertices[(i + 1) % num_vertices] - vertices[i]).normalized();
+        if signed_area > 0.0 { -direction.orth() } else { direction.orth() }
+    };
+    let num_arc_segments = 8;
+    for i in 0..num_vertices {
+        let normal = outward_normal(i);
+        line(&(vertices[i] + normal * radius), &(vertices[(i + 1) % num_vertices] + normal * radius), color, context, gl);
+        let previous_normal = outward_normal((i + num_vertices - 1) % num_vertices);
+        let start_angle = previous_normal.y.atan2(previous_normal.x);
+        let mut delta = normal.y.atan2(normal.x) - start_angle;
+        if delta > f64::consts::PI {
+            delta -= 2.0 * f64::consts::PI;
+        }
+        if delta < -f64::consts::PI {
+            delta += 2.0 * f64::consts::PI;
+        }
+        let arc_point = |j: usize| {
+            let angle = start_angle + delta * (j as f64) / (num_arc_segments as f64);
+            vertices[i] + Point::new(angle.cos(), angle.sin()) * radius
+        };
+        for j in 0..num_arc_segments {
+            line(&arc_point(j), &arc_point(j + 1), color, context, gl);
+        }
+    }
+}
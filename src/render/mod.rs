@@ -6,6 +6,9 @@ use piston_window::{self, Context};
 use self::draw::circle;
 use self::draw::polygon;
 use self::draw::line;
+use self::draw::capsule;
+use self::draw::polyline;
+use self::draw::rounded_polygon;
 use simulation::Simulation;
 use simulation::shape::Shape;
 use point::Point;
@@ -20,14 +23,8 @@ impl Renderer {
     pub fn render(&self, context: Context, gl: &mut GlGraphics, sim: &mut Simulation) {
         piston_window::clear([0.0, 0.0, 0.0, 1.0], gl);
 
-        let center = Point::new(0.0, 0.0);
-
         for body in &sim.bodies {
-            match body.shape {
-                Shape::Circle(ref c) => { circle(self.transform(c.pos), c.radius * self.scale_factor, [1.0, 0.0, 0.0, 1.0], context, gl); }
-                Shape::Polygon(ref p) => { polygon(&p.vertices.iter().map(|&v| self.transform(v)).collect(), [1.0, 0.0, 0.0, 1.0], context, gl); }
-                _ => {}
-            }
+            self.draw_shape(&body.shape, context, gl);
         }
         for coll in &sim.collision_handler.collisions {
             line(&self.transform(coll.info.pos), &self.transform(coll.info.pos + coll.info.normal * coll.info.depth), [0.0, 1.0, 0.0, 1.0], context, gl);
@@ -35,15 +32,46 @@ impl Renderer {
         }
     }
 
+    fn draw_shape(&self, shape: &Shape, context: Context, gl: &mut GlGraphics) {
+        match *shape {
+            Shape::Circle(ref c) => { circle(self.transform(c.pos), c.radius * self.scale_factor, [1.0, 0.0, 0.0, 1.0], context, gl); }
+            Shape::Polygon(ref p) => {
+                let vertices = p.vertices.iter().map(|&v| self.transform(v)).collect();
+                if p.radius > 0.0 { rounded_polygon(&vertices, p.radius * self.scale_factor, [1.0, 0.0, 0.0, 1.0], context, gl); } else { polygon(&vertices, [1.0, 0.0, 0.0, 1.0], context, gl); }
+            }
+            Shape::Segment(ref s) => { line(&self.transform(s.start), &self.transform(s.end), [1.0, 0.0, 0.0, 1.0], context, gl); }
+            Shape::Capsule(ref c) => { capsule(self.transform(c.segment.start), self.transform(c.segment.end), c.radius * self.scale_factor, [1.0, 0.0, 0.0, 1.0], context, gl); }
+            Shape::Chain(ref c) => {
+                let vertices = c.vertices.iter().map(|&v| self.transform(v)).collect();
+                if c.closed { polygon(&vertices, [1.0, 0.0, 0.0, 1.0], context, gl); } else { polyline(&vertices, [1.0, 0.0, 0.0, 1.0], context, gl); }
+            }
+            Shape::Compound(ref c) => {
+                for child in c.children.iter() {
+                    self.draw_shape(child, context, gl);
+                }
+            }
+            Shape::Grid(ref g) => {
+                for child in g.compound.children.iter() {
+                    self.draw_shape(child, context, gl);
+                }
+            }
+        }
+    }
+
     pub fn transform(&self, point: Point) -> Point {
         (point - self.center) * self.scale_factor + Point::new(self.window_dimensions.x / 2.0, self.window_dimensions.y / 2.0)
     }
 
+    // The inverse of transform, from window to world coordinates.
+    pub fn to_world(&self, point: Point) -> Point {
+        (point - Point::new(self.window_dimensions.x / 2.0, self.window_dimensions.y / 2.0)) / self.scale_factor + self.center
+    }
+
     pub fn new(window_dimensions: Point) -> Renderer {
         Renderer { 
             scale_factor: 30.0,
             center: Point::new(0.0, 0.0),
-            window_dimensions: window_dimensions
+            window_dimensions
         }
     }
 }
@@ -0,0 +1,105 @@
+use std::fmt::Debug;
+use std::ops::{Add, Sub, Mul, Div, Neg, AddAssign, SubAssign, MulAssign, DivAssign};
+
+// The number type of points, bodies and the solver. f64 is the default;
+// fixed::Fixed computes the same results on every CPU.
+pub trait Scalar: Copy + Debug + PartialEq + PartialOrd + Send + Sync
+    + Add<Output=Self> + Sub<Output=Self> + Mul<Output=Self> + Div<Output=Self> + Neg<Output=Self>
+    + AddAssign + SubAssign + MulAssign + DivAssign {
+    fn from_f64(value: f64) -> Self;
+    fn to_f64(self) -> f64;
+    // The raw representation, for hashing.
+    fn to_bits(self) -> u64;
+    fn max_value() -> Self;
+    fn min_value() -> Self;
+    fn sqrt(self) -> Self;
+    fn sin(self) -> Self;
+    fn cos(self) -> Self;
+    fn is_finite(self) -> bool;
+
+    fn zero() -> Self {
+        Self::from_f64(0.0)
+    }
+
+    fn one() -> Self {
+        Self::from_f64(1.0)
+    }
+
+    fn from_usize(value: usize) -> Self {
+        Self::from_f64(value as f64)
+    }
+
+    fn abs(self) -> Self {
+        if self < Self::zero() { -self } else { self }
+    }
+
+    fn min(self, other: Self) -> Self {
+        if other < self { other } else { self }
+    }
+
+    fn max(self, other: Self) -> Self {
+        if other > self { other } else { self }
+    }
+
+    fn powi(self, n: i32) -> Self {
+        let mut result = Self::one();
+        for _ in 0..n.abs() {
+            result *= self;
+        }
+        if n < 0 { Self::one() / result } else { result }
+    }
+}
+
+impl Scalar for f64 {
+    fn from_f64(value: f64) -> f64 {
+        value
+    }
+
+    fn to_f64(self) -> f64 {
+        self
+    }
+
+    fn to_bits(self) -> u64 {
+        f64::to_bits(self)
+    }
+
+    fn max_value() -> f64 {
+        f64::MAX
+    }
+
+    fn min_value() -> f64 {
+        f64::MIN
+    }
+
+    fn sqrt(self) -> f64 {
+        f64::sqrt(self)
+    }
+
+    fn sin(self) -> f64 {
+        f64::sin(self)
+    }
+
+    fn cos(self) -> f64 {
+        f64::cos(self)
+    }
+
+    fn is_finite(self) -> bool {
+        f64::is_finite(self)
+    }
+
+    fn abs(self) -> f64 {
+        f64::abs(self)
+    }
+
+    fn min(self, other: f64) -> f64 {
+        f64::min(self, other)
+    }
+
+    fn max(self, other: f64) -> f64 {
+        f64::max(self, other)
+    }
+
+    fn powi(self, n: i32) -> f64 {
+        f64::powi(self, n)
+    }
+}
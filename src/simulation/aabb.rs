@@ -0,0 +1,31 @@
+use point::Point;
+use scalar::Scalar;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Aabb<S = f64> {
+    pub min: Point<S>,
+    pub max: Point<S>
+}
+
+impl<S: Scalar> Aabb<S> {
+    pub fn from_points(points: &[Point<S>]) -> Aabb<S> {
+        let mut min = points[0];
+        let mut max = points[0];
+        for point in points[1..].iter() {
+            min.x = min.x.min(point.x);
+            min.y = min.y.min(point.y);
+            max.x = max.x.max(point.x);
+            max.y = max.y.max(point.y);
+        }
+        Aabb { min, max }
+    }
+
+    pub fn expand(&self, margin: S) -> Aabb<S> {
+        let offset = Point::new(margin, margin);
+        Aabb { min: self.min - offset, max: self.max + offset }
+    }
+
+    pub fn overlaps(&self, other: &Aabb<S>) -> bool {
+        self.min.x <= other.max.x && other.min.x <= self.max.x && self.min.y <= other.max.y && other.min.y <= self.max.y
+    }
+}
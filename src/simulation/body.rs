@@ -1,52 +1,76 @@
 use std::fmt;
 use point::Point;
+use scalar::Scalar;
 use simulation::shape::Shape;
 use simulation::polygon::Polygon;
 use simulation::circle::Circle;
+use simulation::segment::Segment;
+use simulation::capsule::Capsule;
+use simulation::chain::Chain;
+use simulation::compound::Compound;
+use simulation::polygon;
+use simulation::decomposition;
+use simulation::grid::{Cells, Grid};
+use std::io;
+use std::path::Path;
+use simulation::filter::CollisionFilter;
 use std::f64;
+use super::{MIN_BREAKING_AREA, FRICTION, RESTITUTION};
 
-pub struct Body {
-    pub pos: Point,
-    pub vel: Point,
-    pub acc: Point,
-    pub apos: f64,
-    pub avel: f64,
-    pub aacc: f64,
-    pub mass: f64,
-    pub inertia: f64,
-    pub shape: Shape,
+#[derive(Clone)]
+pub struct Body<S = f64> {
+    pub pos: Point<S>,
+    pub vel: Point<S>,
+    pub acc: Point<S>,
+    pub apos: S,
+    pub avel: S,
+    pub aacc: S,
+    pub mass: S,
+    pub inertia: S,
+    pub shape: Shape<S>,
     pub is_static: bool,
+    pub is_sensor: bool,
+    pub gravity_scale: S,
+    pub filter: CollisionFilter,
+    // Local direction of the side from which other bodies can collide with this body.
+    pub one_way_direction: Option<Point<S>>,
+    // Polygons break into shards if the normal impulse on them in one step exceeds this.
+    pub breaking_impulse: Option<S>,
+    // A collision uses the geometric mean of the friction of both bodies and the
+    // larger of their restitutions.
+    pub friction: S,
+    pub restitution: S,
 }
 
-impl Body {
-    pub fn timestep(&mut self, dt : f64) {
+impl<S: Scalar> Body<S> {
+    pub fn timestep(&mut self, dt : S) {
         if self.is_static {
             return
         }
         self.vel += self.acc * dt;
         self.pos += self.vel * dt;
-        self.acc = Point { x: 0.0, y: 0.0 };
+        self.acc = Point::zero();
         self.avel += self.aacc * dt;
         self.apos += self.avel * dt;
-        self.aacc = 0.0;
+        self.aacc = S::zero();
         self.shape.update_pos(self.pos, self.apos);
     }
 
-    pub fn apply_force(&mut self, force : Point) {
+    pub fn apply_force(&mut self, force : Point<S>) {
         if self.is_static {
             return
         }
         self.acc += force / self.mass;
     }
 
-    pub fn apply_impulse(&mut self, impulse : Point) {
+    pub fn apply_impulse(&mut self, impulse : Point<S>) {
         if self.is_static {
             return
         }
         self.vel += impulse / self.mass;
     }
 
-    pub fn apply_force_at(&mut self, force : Point, pos: Point) {
+    pub fn apply_force_at(&mut self, force : Point<S>, pos: Point<S>) {
         if self.is_static {
             return
         }
@@ -54,7 +78,7 @@ impl Body {
         self.aacc += pos.orth() * force / self.inertia;
     }
 
-    pub fn apply_impulse_at(&mut self, impulse : Point, pos: Point) {
+    pub fn apply_impulse_at(&mut self, impulse : Point<S>, pos: Point<S>) {
         if self.is_static {
             return
         }
@@ -62,82 +86,203 @@ impl Body {
         self.avel += pos.orth() * impulse / self.inertia;
     }
 
-    pub fn vel_at(&mut self, relative_pos: Point) -> Point {
-        self.vel + self.avel * relative_pos.orth()
+    pub fn vel_at(&self, relative_pos: Point<S>) -> Point<S> {
+        self.vel + relative_pos.orth() * self.avel
     }
 
-    pub fn new(pos: Point, mass: f64, shape: Shape, is_static: bool) -> Body {
+    pub fn new(pos: Point<S>, mass: S, shape: Shape<S>, is_static: bool) -> Body<S> {
+        if !is_static && shape.is_static_only() {
+            panic!("Body::new - shape can only be used for static bodies");
+        }
+        // Place the shape exactly where update_pos would, so that a body built from
+        // the offsets of its shape (as in a scene file) is identical to this one.
+        let mut shape = shape;
+        shape.update_pos(pos, S::zero());
         Body {
             pos,
             mass,
-            vel: Point { x: 0.0, y: 0.0 },
-            acc: Point { x: 0.0, y: 0.0 },
-            apos: 0.0,
-            avel: 0.0,
-            aacc: 0.0,
+            vel: Point::zero(),
+            acc: Point::zero(),
+            apos: S::zero(),
+            avel: S::zero(),
+            aacc: S::zero(),
             inertia: shape.get_moment_of_inertia() * mass,
             shape,
             is_static,
+            is_sensor: false,
+            gravity_scale: S::one(),
+            filter: CollisionFilter::new(),
+            one_way_direction: None,
+            breaking_impulse: None,
+            friction: S::from_f64(FRICTION),
+            restitution: S::from_f64(RESTITUTION),
         }
     }
 
-    pub fn inv_quantity(&self, quantity: f64) -> f64 {
+    pub fn inv_quantity(&self, quantity: S) -> S {
         if self.is_static {
-            0.0
+            S::zero()
         }
         else {
-            1.0 / quantity
+            S::one() / quantity
         }
     }
 
-    pub fn inv_mass(&self) -> f64 {
-        return self.inv_quantity(self.mass)
+    pub fn inv_mass(&self) -> S {
+        self.inv_quantity(self.mass)
     }
 
-    pub fn inv_inertia(&self) -> f64 {
-        return self.inv_quantity(self.inertia)
+    pub fn inv_inertia(&self) -> S {
+        self.inv_quantity(self.inertia)
     }
 }
 
-pub fn get_circle(pos: Point, mass: f64, radius: f64) -> Body {
-    Body::new(pos, mass, Shape::Circle(Circle{ pos: pos, radius:radius }), false)
+pub fn get_circle<S: Scalar>(pos: Point<S>, mass: S, radius: S) -> Body<S> {
+    Body::new(pos, mass, Shape::Circle(Circle::new(pos, radius)), false)
+}
+
+pub fn get_segment<S: Scalar>(start: Point<S>, end: Point<S>, mass: S) -> Body<S> {
+    let segment = Segment::new(start, end);
+    Body::new(segment.pos, mass, Shape::Segment(segment), mass == S::zero())
+}
+
+pub fn get_capsule<S: Scalar>(start: Point<S>, end: Point<S>, radius: S, mass: S) -> Body<S> {
+    let capsule = Capsule::new(start, end, radius);
+    Body::new(capsule.segment.pos, mass, Shape::Capsule(capsule), mass == S::zero())
+}
+
+pub fn get_chain<S: Scalar>(vertices: Vec<Point<S>>, closed: bool) -> Result<Body<S>, String> {
+    let chain = Chain::new(vertices, closed)?;
+    Ok(Body::new(chain.pos, S::zero(), Shape::Chain(chain), true))
 }
 
-pub fn get_polygon(vertices: Vec<Point>, mass: f64) -> Body {
+pub fn get_polygon<S: Scalar>(vertices: Vec<Point<S>>, mass: S) -> Body<S> {
     let poly = Polygon::new(vertices);
-    Body::new(poly.pos, mass, Shape::Polygon(poly), if (mass == 0.0) { true } else { false })
+    Body::new(poly.pos, mass, Shape::Polygon(poly), mass == S::zero())
+}
+
+// Accepts any simple polygon in either winding. Concave polygons are split into
+// convex pieces which make up a compound shape.
+pub fn get_concave_polygon<S: Scalar>(vertices: Vec<Point<S>>, mass: S) -> Result<Body<S>, String> {
+    let validation = polygon::validate(&vertices);
+    if validation.is_self_intersecting || validation.winding == polygon::Winding::Degenerate {
+        return Err("polygon is self intersecting or has no area".to_string())
+    }
+    let mut vertices = vertices;
+    if validation.winding == polygon::Winding::Clockwise {
+        vertices.reverse();
+    }
+    if validation.is_convex {
+        return Ok(get_polygon(vertices, mass))
+    }
+    let pieces = decomposition::decompose(&vertices).into_iter().map(Polygon::new).collect();
+    let compound = Compound::new(pieces);
+    Ok(Body::new(compound.pos, mass, Shape::Compound(compound), mass == S::zero()))
+}
+
+// The top left corner of the cells is placed at origin. The mass is the sum of
+// the densities of the cells times their area.
+pub fn get_grid<S: Scalar>(origin: Point<S>, cells: Cells, is_static: bool) -> Result<Body<S>, String> {
+    let mass = S::from_f64(cells.get_mass());
+    let grid = Grid::new(cells, origin)?;
+    Ok(Body::new(grid.pos, mass, Shape::Grid(grid), is_static))
+}
+
+// A part of a grid body, with cells that start at offset relative to the top left
+// corner of the cells of the parent. The part keeps the properties of the parent
+// and moves with the velocity of the parent at its center of mass.
+pub fn get_grid_fragment<S: Scalar>(parent: &Body<S>, offset: Point, cells: Cells) -> Body<S> {
+    let pos = match parent.shape {
+        Shape::Grid(ref grid) => { grid.to_world(Point::from_f64(offset + cells.get_center_of_mass())) }
+        _ => { panic!("get_grid_fragment - parent is not a grid") }
+    };
+    let mut fragment = get_grid(Point::zero(), cells, parent.is_static).expect("get_grid_fragment - part has no solid cells");
+    fragment.pos = pos;
+    fragment.apos = parent.apos;
+    fragment.shape.update_pos(pos, parent.apos);
+    if !parent.is_static {
+        fragment.vel = parent.vel_at(pos - parent.pos);
+        fragment.avel = parent.avel;
+    }
+    fragment.is_sensor = parent.is_sensor;
+    fragment.gravity_scale = parent.gravity_scale;
+    fragment.filter = parent.filter;
+    fragment.one_way_direction = parent.one_way_direction;
+    fragment.friction = parent.friction;
+    fragment.restitution = parent.restitution;
+    fragment.shape.set_surface_velocity(parent.shape.get_surface_velocity());
+    fragment
+}
+
+// A piece of a polygon body with vertices in world coordinates. Its mass and its
+// breaking impulse are the share of its area and it moves with the velocity of
+// the parent at its center. Shards smaller than MIN_BREAKING_AREA do not break.
+pub fn get_shard<S: Scalar>(parent: &Body<S>, vertices: Vec<Point<S>>) -> Body<S> {
+    let (area, radius) = match parent.shape {
+        Shape::Polygon(ref polygon) => { (polygon.get_area(), polygon.radius) }
+        _ => { panic!("get_shard - parent is not a polygon") }
+    };
+    let shard = Polygon::with_radius(vertices, radius);
+    let shard_area = shard.get_area();
+    let mass = parent.mass * shard_area / area;
+    let mut body = Body::new(shard.pos, mass, Shape::Polygon(shard), parent.is_static);
+    if !parent.is_static {
+        body.vel = parent.vel_at(body.pos - parent.pos);
+        body.avel = parent.avel;
+    }
+    body.is_sensor = parent.is_sensor;
+    body.gravity_scale = parent.gravity_scale;
+    body.filter = parent.filter;
+    body.one_way_direction = parent.one_way_direction;
+    body.friction = parent.friction;
+    body.restitution = parent.restitution;
+    if shard_area >= S::from_f64(MIN_BREAKING_AREA) {
+        body.breaking_impulse = parent.breaking_impulse.map(|breaking_impulse| breaking_impulse * shard_area / area);
+    }
+    body.shape.set_surface_velocity(parent.shape.get_surface_velocity());
+    body
+}
+
+pub fn get_grid_from_file<S: Scalar, P: AsRef<Path>>(origin: Point<S>, path: P, cell_size: f64, is_static: bool) -> io::Result<Body<S>> {
+    get_grid(origin, Cells::load(path, cell_size)?, is_static).map_err(|message| io::Error::new(io::ErrorKind::InvalidData, message))
+}
+
+pub fn get_rounded_polygon<S: Scalar>(vertices: Vec<Point<S>>, radius: S, mass: S) -> Body<S> {
+    let poly = Polygon::with_radius(vertices, radius);
+    Body::new(poly.pos, mass, Shape::Polygon(poly), mass == S::zero())
 }
 
-pub fn get_rectangle(pos: Point, width: f64, height: f64, mass: f64) -> Body {
+pub fn get_rectangle<S: Scalar>(pos: Point<S>, width: S, height: S, mass: S) -> Body<S> {
+    let (half_width, half_height) = (width / S::from_f64(2.0), height / S::from_f64(2.0));
     let vertices = [
-        Point::new(pos.x - width / 2.0, pos.y - height / 2.0),
-        Point::new(pos.x + width / 2.0, pos.y - height / 2.0),
-        Point::new(pos.x + width / 2.0, pos.y + height / 2.0),
-        Point::new(pos.x - width / 2.0, pos.y + height / 2.0),
+        Point::new(pos.x - half_width, pos.y - half_height),
+        Point::new(pos.x + half_width, pos.y - half_height),
+        Point::new(pos.x + half_width, pos.y + half_height),
+        Point::new(pos.x - half_width, pos.y + half_height),
     ];
     get_polygon(vertices.to_vec(), mass)
 }
 
-pub fn get_regular_polygon(pos: Point, radius: f64, num_vertices: usize, mass: f64) -> Body {
+pub fn get_regular_polygon<S: Scalar>(pos: Point<S>, radius: S, num_vertices: usize, mass: S) -> Body<S> {
     let mut vertices = vec![];
     for j in 0..num_vertices {
-        let angle = 2.0 * f64::consts::PI * (j as f64) / (num_vertices as f64);
+        let angle = S::from_f64(2.0 * f64::consts::PI * (j as f64) / (num_vertices as f64));
         vertices.push(Point{
             x: pos.x + radius * angle.cos(),
             y: pos.y + radius * angle.sin()
         });
     }
     let poly = Polygon::new(vertices);
-    Body::new(poly.pos, mass, Shape::Polygon(poly), if (mass == 0.0) { true } else { false })
+    Body::new(poly.pos, mass, Shape::Polygon(poly), mass == S::zero())
 }
 
-impl fmt::Debug for Body {
+impl<S: Scalar> fmt::Debug for Body<S> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         if self.is_static {
-            write!(f, "Wall at ({}, {}) with mass {} and inertia {}", self.pos.x, self.pos.y, self.mass, self.inertia)
+            write!(f, "Wall at ({}, {}) with mass {} and inertia {}", self.pos.x.to_f64(), self.pos.y.to_f64(), self.mass.to_f64(), self.inertia.to_f64())
         }
         else {
-            write!(f, "Body at ({}, {}) with mass {} and inertia {}", self.pos.x, self.pos.y, self.mass, self.inertia)
+            write!(f, "Body at ({}, {}) with mass {} and inertia {}", self.pos.x.to_f64(), self.pos.y.to_f64(), self.mass.to_f64(), self.inertia.to_f64())
         }
     }
 }
@@ -0,0 +1,70 @@
+use point::Point;
+use scalar::Scalar;
+use simulation::aabb::Aabb;
+use simulation::segment::Segment;
+use std::f64;
+
+#[derive(Debug, Clone)]
+pub struct Capsule<S = f64> {
+    pub segment: Segment<S>,
+    pub radius: S
+}
+
+impl<S: Scalar> Capsule<S> {
+    pub fn new(start: Point<S>, end: Point<S>, radius: S) -> Capsule<S> {
+        Capsule {
+            segment: Segment::new(start, end),
+            radius
+        }
+    }
+
+    pub fn project(&self, normal: Point<S>) -> [S;2] {
+        let projected = self.segment.project(normal);
+        [projected[0] - self.radius, projected[1] + self.radius]
+    }
+
+    pub fn update_pos(&mut self, pos: Point<S>, apos: S) {
+        self.segment.update_pos(pos, apos)
+    }
+
+    pub fn get_aabb(&self) -> Aabb<S> {
+        self.segment.get_aabb().expand(self.radius)
+    }
+
+    pub fn get_moment_of_inertia(&self) -> S {
+        // A box of size length x 2 radius and two half circles at its ends.
+        // The half circles are moved from their centroid to the end of the box
+        // with the parallel axis theorem.
+        let length = self.segment.length();
+        let radius_sqr = self.radius.powi(2);
+        let pi = S::from_f64(f64::consts::PI);
+        let circle_area = pi * radius_sqr;
+        let box_area = S::from_f64(2.0) * self.radius * length;
+        let centroid_offset = S::from_f64(4.0) * self.radius / (S::from_f64(3.0) * pi);
+        let half_length = S::from_f64(0.5) * length;
+        let circle_inertia = circle_area * (S::from_f64(0.5) * radius_sqr + half_length.powi(2) + S::from_f64(2.0) * half_length * centroid_offset);
+        let box_inertia = box_area * (S::from_f64(4.0) * radius_sqr + length.powi(2)) / S::from_f64(12.0);
+        (circle_inertia + box_inertia) / (circle_area + box_area)
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    #[test]
+    fn test_moment_of_inertia_degenerate_capsule() {
+        let capsule = Capsule::new(Point::new(1.0, 1.0), Point::new(1.0, 1.0), 2.0);
+        assert!((capsule.get_moment_of_inertia() - 2.0).abs() < 0.00001);
+    }
+
+    #[test]
+    fn test_projection() {
+        let capsule = Capsule::new(Point::new(0.0, 0.0), Point::new(2.0, 0.0), 0.5);
+        let projection = capsule.project(Point::new(1.0, 0.0));
+        assert_eq!(projection[0], -0.5);
+        assert_eq!(projection[1], 2.5);
+        let projection = capsule.project(Point::new(0.0, 1.0));
+        assert_eq!(projection[0], -0.5);
+        assert_eq!(projection[1], 0.5);
+    }
+}
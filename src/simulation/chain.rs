@@ -0,0 +1,115 @@
+use point::Point;
+use scalar::Scalar;
+use simulation::aabb::Aabb;
+
+// A polyline of segments without thickness. The vertices adjacent to a segment
+// (ghost vertices) are used to avoid catching on the joints between segments.
+#[derive(Debug, Clone)]
+pub struct Chain<S = f64> {
+    pub pos: Point<S>,
+    pub vertices: Vec<Point<S>>,
+    pub closed: bool,
+    // See Shape::get_surface_velocity.
+    pub surface_velocity: S,
+    offsets: Vec<Point<S>>
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ChainSegment<S = f64> {
+    pub start: Point<S>,
+    pub end: Point<S>,
+    pub previous: Option<Point<S>>,
+    pub next: Option<Point<S>>
+}
+
+// Open chains need at least one segment, closed chains at least a triangle.
+pub fn check_vertices<S>(vertices: &[Point<S>], closed: bool) -> Result<(), String> {
+    if vertices.len() < 2 {
+        return Err("chains need at least two vertices".to_string())
+    }
+    if closed && vertices.len() < 3 {
+        return Err("closed chains need at least three vertices".to_string())
+    }
+    Ok(())
+}
+
+impl<S: Scalar> Chain<S> {
+    pub fn new(vertices: Vec<Point<S>>, closed: bool) -> Result<Chain<S>, String> {
+        check_vertices(&vertices, closed)?;
+        let pos = vertices.iter().fold(Point::zero(), |acc, &x| acc + x) / S::from_usize(vertices.len());
+        let offsets = vertices.iter().map(|x| (*x) - pos).collect();
+        Ok(Chain {
+            pos,
+            vertices,
+            closed,
+            surface_velocity: S::zero(),
+            offsets
+        })
+    }
+
+    // A chain at the origin with vertices given relative to its position.
+    pub fn from_offsets(offsets: Vec<Point<S>>, closed: bool) -> Chain<S> {
+        Chain {
+            pos: Point::zero(),
+            vertices: offsets.clone(),
+            closed,
+            surface_velocity: S::zero(),
+            offsets
+        }
+    }
+
+    pub fn get_offsets(&self) -> &[Point<S>] {
+        &self.offsets
+    }
+
+    pub fn num_segments(&self) -> usize {
+        if self.closed { self.vertices.len() } else { self.vertices.len() - 1 }
+    }
+
+    pub fn get_segment(&self, index: usize) -> ChainSegment<S> {
+        let num_vertices = self.vertices.len();
+        let vertex = |i: usize| self.vertices[i % num_vertices];
+        let has_previous = self.closed || index > 0;
+        let has_next = self.closed || index + 2 < num_vertices;
+        ChainSegment {
+            start: vertex(index),
+            end: vertex(index + 1),
+            previous: if has_previous { Some(vertex(index + num_vertices - 1)) } else { None },
+            next: if has_next { Some(vertex(index + 2)) } else { None }
+        }
+    }
+
+    pub fn project(&self, normal: Point<S>) -> [S;2] {
+        let projected: Vec<S> = self.vertices.iter().map(|&v| v * normal).collect();
+        [projected.iter().cloned().fold(S::max_value(), S::min), projected.iter().cloned().fold(S::min_value(), S::max)]
+    }
+
+    pub fn update_pos(&mut self, pos: Point<S>, apos: S) {
+        self.pos = pos;
+        self.vertices = self.offsets.iter().map(|x| ((*x).rotate(apos)) + pos).collect();
+    }
+
+    pub fn get_aabb(&self) -> Aabb<S> {
+        Aabb::from_points(&self.vertices)
+    }
+
+    pub fn get_moment_of_inertia(&self) -> S {
+        // Chains are only used for static bodies.
+        S::zero()
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn test_too_few_vertices() {
+        assert!(Chain::<f64>::new(vec![], false).is_err());
+        assert!(Chain::new(vec![Point::new(0.0, 0.0)], false).is_err());
+        assert!(Chain::new(vec![Point::new(0.0, 0.0), Point::new(1.0, 0.0)], true).is_err());
+        let chain = Chain::new(vec![Point::new(0.0, 0.0), Point::new(1.0, 0.0)], false).unwrap();
+        assert_eq!(chain.num_segments(), 1);
+        assert_eq!(chain.pos, Point::new(0.5, 0.0));
+    }
+}
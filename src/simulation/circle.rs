@@ -1,18 +1,30 @@
 use point::Point;
+use scalar::Scalar;
+use simulation::aabb::Aabb;
 
-#[derive(Debug)]
-pub struct Circle {
-    pub pos: Point,
-    pub radius: f64
+#[derive(Debug, Clone)]
+pub struct Circle<S = f64> {
+    pub pos: Point<S>,
+    pub radius: S,
+    // See Shape::get_surface_velocity.
+    pub surface_velocity: S
 }
 
-impl Circle {
-    pub fn project(&self, normal: Point) -> [f64;2] {
+impl<S: Scalar> Circle<S> {
+    pub fn new(pos: Point<S>, radius: S) -> Circle<S> {
+        Circle { pos, radius, surface_velocity: S::zero() }
+    }
+
+    pub fn project(&self, normal: Point<S>) -> [S;2] {
         let projected = self.pos * normal;
         [projected - self.radius, projected + self.radius]
     }
 
-    pub fn get_moment_of_inertia(&self) -> f64 {
-        return 0.5 * self.radius.powi(2)
+    pub fn get_aabb(&self) -> Aabb<S> {
+        Aabb::from_points(&[self.pos]).expand(self.radius)
+    }
+
+    pub fn get_moment_of_inertia(&self) -> S {
+        S::from_f64(0.5) * self.radius.powi(2)
     }
 }
@@ -1,221 +1,669 @@
 use simulation::body::Body;
 use point::Point;
-use simulation::shape::Shape;
+use scalar::Scalar;
+use simulation::shape::{Shape, Project};
+use simulation::polygon;
 use simulation::polygon::Polygon;
-use simulation::circle::Circle;
-use simulation::collisions::CollisionInfo;
-
-pub fn find_collisions(body1: &Body, body2: &Body) -> Vec<CollisionInfo> {
-    let mut collisions = vec![];
-    match body1.shape {
-        Shape::Circle(ref circle1) => {
-            match body2.shape {
-                Shape::Circle(ref circle2) => { 
-                    collisions.push(circle_circle(&circle1, &circle2));
-                }
-                _ => {}
+use simulation::chain::{Chain, ChainSegment};
+use simulation::compound::Compound;
+use simulation::segment::Segment;
+use simulation::aabb::Aabb;
+use simulation::collisions::{CollisionInfo, clamp};
+use simulation::gjk;
+use simulation::gjk::DistanceResult;
+use super::{PARALLEL_TOLERANCE, AXIS_TOLERANCE, FACE_TOLERANCE, DISTANCE_TOLERANCE, COLLISION_MARGIN};
+
+pub fn find_collisions<S: Scalar>(body1: &Body<S>, body2: &Body<S>) -> Vec<CollisionInfo<S>> {
+    shape_shape(&body1.shape, &body2.shape)
+}
+
+fn shape_shape<S: Scalar>(shape1: &Shape<S>, shape2: &Shape<S>) -> Vec<CollisionInfo<S>> {
+    match (shape1, shape2) {
+        (Shape::Compound(compound), shape) => { compound_shape(compound, shape) }
+        (shape, Shape::Compound(compound)) => { flip(compound_shape(compound, shape)) }
+        (Shape::Grid(grid), shape) => { compound_shape(&grid.compound, shape) }
+        (shape, Shape::Grid(grid)) => { flip(compound_shape(&grid.compound, shape)) }
+        (shape1, shape2) => {
+            simple_shape_shape(shape1, shape2).into_iter().map(|c| CollisionInfo {
+                surface_velocity1: shape1.get_surface_velocity(),
+                surface_velocity2: shape2.get_surface_velocity(),
+                ..c
+            }).collect()
+        }
+    }
+}
+
+// Shapes other than compounds and grids.
+fn simple_shape_shape<S: Scalar>(shape1: &Shape<S>, shape2: &Shape<S>) -> Vec<CollisionInfo<S>> {
+    match (shape1, shape2) {
+        (Shape::Chain(_), Shape::Chain(_)) => { vec![] }
+        (Shape::Chain(chain), shape) => { chain_shape(chain, shape) }
+        (shape, Shape::Chain(chain)) => { flip(chain_shape(chain, shape)) }
+        (Shape::Polygon(polygon1), Shape::Polygon(polygon2)) => {
+            let collisions = polygon_polygon(polygon1, polygon2);
+            if collisions.is_empty() && polygon1.radius + polygon2.radius > S::zero() {
+                rounded_polygon_polygon(polygon1, polygon2)
+            }
+            else {
+                collisions
             }
         }
-        Shape::Polygon(ref polygon1) => {
-            match body2.shape {
-                Shape::Polygon(ref polygon2) => { 
-                    let colls = polygon_polygon(&polygon1, &polygon2);
-                    collisions.push(colls.0);
-                    collisions.push(colls.1);
-                }
-                _ => {}
+        (Shape::Polygon(polygon), shape) if shape.as_rounded_segment().is_some() => {
+            let (start, end, radius) = shape.as_rounded_segment().unwrap();
+            polygon_rounded_segment(polygon, start, end, radius)
+        }
+        (shape, Shape::Polygon(polygon)) if shape.as_rounded_segment().is_some() => {
+            let (start, end, radius) = shape.as_rounded_segment().unwrap();
+            flip(polygon_rounded_segment(polygon, start, end, radius))
+        }
+        (shape1, shape2) => {
+            // Circles, segments and capsules.
+            let (start1, end1, radius1) = shape1.as_rounded_segment().unwrap();
+            let (start2, end2, radius2) = shape2.as_rounded_segment().unwrap();
+            rounded_segment_rounded_segment(start1, end1, radius1, start2, end2, radius2)
+        }
+    }
+}
+
+pub fn distance<S: Scalar>(body1: &Body<S>, body2: &Body<S>) -> DistanceResult<S> {
+    shape_distance(&body1.shape, &body2.shape)
+}
+
+fn shape_distance<S: Scalar>(shape1: &Shape<S>, shape2: &Shape<S>) -> DistanceResult<S> {
+    // GJK needs convex shapes, so compounds, grids and chains are measured part by part.
+    match (shape1, shape2) {
+        (Shape::Compound(compound), shape) => { closest(compound.children.iter().map(|child| shape_distance(child, shape))) }
+        (shape, Shape::Compound(compound)) => { closest(compound.children.iter().map(|child| shape_distance(shape, child))) }
+        (Shape::Grid(grid), shape) => { closest(grid.compound.children.iter().map(|child| shape_distance(child, shape))) }
+        (shape, Shape::Grid(grid)) => { closest(grid.compound.children.iter().map(|child| shape_distance(shape, child))) }
+        (Shape::Chain(chain), shape) => { closest(chain_segments(chain).iter().map(|segment| shape_distance(segment, shape))) }
+        (shape, Shape::Chain(chain)) => { closest(chain_segments(chain).iter().map(|segment| shape_distance(shape, segment))) }
+        (shape1, shape2) => { gjk::distance(shape1, shape2) }
+    }
+}
+
+fn chain_segments<S: Scalar>(chain: &Chain<S>) -> Vec<Shape<S>> {
+    (0..chain.num_segments()).map(|i| {
+        let segment = chain.get_segment(i);
+        Shape::Segment(Segment::new(segment.start, segment.end))
+    }).collect()
+}
+
+fn closest<S: Scalar, I: Iterator<Item = DistanceResult<S>>>(results: I) -> DistanceResult<S> {
+    results.fold(None, |best: Option<DistanceResult<S>>, result| {
+        if best.is_some_and(|best| best.distance <= result.distance) { best } else { Some(result) }
+    }).unwrap()
+}
+
+fn compound_shape<S: Scalar>(compound: &Compound<S>, shape: &Shape<S>) -> Vec<CollisionInfo<S>> {
+    // Normals point from the compound to the shape.
+    let aabb = shape.get_aabb();
+    compound.children.iter()
+        .filter(|child| child.get_aabb().overlaps(&aabb))
+        .flat_map(|child| shape_shape(child, shape))
+        .collect()
+}
+
+fn flip<S: Scalar>(collisions: Vec<CollisionInfo<S>>) -> Vec<CollisionInfo<S>> {
+    collisions.into_iter().map(|c| CollisionInfo {
+        normal: -c.normal,
+        surface_velocity1: c.surface_velocity2,
+        surface_velocity2: c.surface_velocity1,
+        ..c
+    }).collect()
+}
+
+pub fn closest_point_on_segment<S: Scalar>(point: Point<S>, start: Point<S>, end: Point<S>) -> Point<S> {
+    let direction = end - start;
+    let length_sqr = direction * direction;
+    if length_sqr == S::zero() {
+        return start
+    }
+    start + direction * clamp(S::zero(), (point - start) * direction / length_sqr, S::one())
+}
+
+pub fn closest_points_segments<S: Scalar>(start1: Point<S>, end1: Point<S>, start2: Point<S>, end2: Point<S>) -> (Point<S>, Point<S>) {
+    // See Ericson, Real-Time Collision Detection, 5.1.9
+    let d1 = end1 - start1;
+    let d2 = end2 - start2;
+    let r = start1 - start2;
+    let a = d1 * d1;
+    let e = d2 * d2;
+    let f = d2 * r;
+    if a == S::zero() {
+        return (start1, closest_point_on_segment(start1, start2, end2))
+    }
+    if e == S::zero() {
+        return (closest_point_on_segment(start2, start1, end1), start2)
+    }
+    let b = d1 * d2;
+    let c = d1 * r;
+    let denominator = a * e - b * b;
+    let mut s = if denominator != S::zero() { clamp(S::zero(), (b * f - c * e) / denominator, S::one()) } else { S::zero() };
+    let mut t = (b * s + f) / e;
+    if t < S::zero() {
+        t = S::zero();
+        s = clamp(S::zero(), -c / a, S::one());
+    }
+    else if t > S::one() {
+        t = S::one();
+        s = clamp(S::zero(), (b - c) / a, S::one());
+    }
+    (start1 + d1 * s, start2 + d2 * t)
+}
+
+fn is_parallel<S: Scalar>(direction1: Point<S>, direction2: Point<S>) -> bool {
+    (direction1.orth() * direction2).abs() < S::from_f64(PARALLEL_TOLERANCE) * direction1.norm() * direction2.norm()
+}
+
+fn clip_to_interval<S: Scalar>(start: Point<S>, end: Point<S>, axis: Point<S>, min: S, max: S) -> Vec<Point<S>> {
+    // Clip the segment between start and end to the part whose projection onto axis is in [min, max].
+    let projected_start = start * axis;
+    let projected_end = end * axis;
+    if (projected_end - projected_start).abs() == S::zero() {
+        return if projected_start >= min && projected_start <= max { vec![start] } else { vec![] }
+    }
+    let point_at = |projected: S| start + (end - start) * ((projected - projected_start) / (projected_end - projected_start));
+    let lower = min.max(projected_start.min(projected_end));
+    let upper = max.min(projected_start.max(projected_end));
+    if lower > upper {
+        vec![]
+    }
+    else {
+        vec![point_at(lower), point_at(upper)]
+    }
+}
+
+fn rounded_segment_rounded_segment<S: Scalar>(start1: Point<S>, end1: Point<S>, radius1: S, start2: Point<S>, end2: Point<S>, radius2: S) -> Vec<CollisionInfo<S>> {
+    // Normals point from the first to the second shape.
+    let radius = radius1 + radius2;
+    if radius == S::zero() {
+        return segment_segment(start1, end1, start2, end2)
+    }
+    let direction1 = end1 - start1;
+    let direction2 = end2 - start2;
+    if direction1 * direction1 > S::zero() && direction2 * direction2 > S::zero() && is_parallel(direction1, direction2) {
+        // Two points are needed so that capsules lying on each other are stable.
+        let axis = direction1.normalized();
+        let mut normal = axis.orth();
+        if (start2.middle(end2) - start1) * normal < S::zero() {
+            normal = -normal;
+        }
+        let clipped = clip_to_interval(start2, end2, axis, (start1 * axis).min(end1 * axis), (start1 * axis).max(end1 * axis));
+        let collisions: Vec<CollisionInfo<S>> = clipped.iter().filter_map(|&point| {
+            let depth = radius - (point - start1) * normal;
+            if depth > S::zero() {
+                Some(CollisionInfo::new(point - normal * (radius2 - depth * S::from_f64(0.5)), depth, normal))
             }
+            else {
+                None
+            }
+        }).collect();
+        if !collisions.is_empty() {
+            return collisions
         }
     }
-    collisions.iter().filter_map(|&x| x).collect()
+    let (closest1, closest2) = closest_points_segments(start1, end1, start2, end2);
+    let distance = (closest2 - closest1).norm();
+    if distance >= radius {
+        return vec![]
+    }
+    let normal = if distance > S::zero() {
+        (closest2 - closest1) / distance
+    }
+    else {
+        // The cores intersect, so there is no meaningful closest direction.
+        let difference = start2.middle(end2) - start1.middle(end1);
+        if difference.norm() > S::zero() { difference.normalized() } else { Point::new(S::zero(), S::one()) }
+    };
+    let depth = radius - distance;
+    vec![CollisionInfo::new(closest2 - normal * (radius2 - depth * S::from_f64(0.5)), depth, normal)]
 }
 
-// fn circle_circle(circle1: &Circle, circle2: &Circle) -> Option<Collision> {
-//     let normal = circle1.pos - circle2.pos;
-//     let distance = normal.norm();
-//     let depth = distance - (circle1.radius + circle2.radius);
-//     if depth < 0.0 {
-//         Some(Collision { 
-//             pos: circle1.pos.middle(circle2.pos),
-//             depth: depth,
-//             normal: normal
-//         })
-//     }
-//     else {
-//         None
-//     }
-// }
+fn segment_segment<S: Scalar>(start1: Point<S>, end1: Point<S>, start2: Point<S>, end2: Point<S>) -> Vec<CollisionInfo<S>> {
+    // Segments without thickness only touch where they cross. They are pushed
+    // apart along the normal of either segment, whichever needs the shorter
+    // push, at the end point of the other segment that has to be moved.
+    if !polygon::segments_intersect(start1, end1, start2, end2) {
+        return vec![]
+    }
+    let mut best: Option<CollisionInfo<S>> = None;
+    for &(start, end, other_start, other_end, sign) in [(start1, end1, start2, end2, S::one()), (start2, end2, start1, end1, -S::one())].iter() {
+        let direction = end - start;
+        if direction * direction == S::zero() {
+            continue
+        }
+        let axis = direction.normalized().orth();
+        let (projected_start, projected_end) = ((other_start - start) * axis, (other_end - start) * axis);
+        // Push the other segment along the axis until its lower end point is on
+        // the line, or against it until its upper end point is.
+        let (push, depth, point) = if -projected_start.min(projected_end) < projected_start.max(projected_end) {
+            if projected_start < projected_end { (axis, -projected_start, other_start) } else { (axis, -projected_end, other_end) }
+        }
+        else if projected_start > projected_end {
+            (-axis, projected_start, other_start)
+        }
+        else {
+            (-axis, projected_end, other_end)
+        };
+        if best.is_none_or(|best| depth < best.depth) {
+            // The normal points from the first to the second segment.
+            best = Some(CollisionInfo::new(point + push * (depth * S::from_f64(0.5)), depth, push * sign));
+        }
+    }
+    best.into_iter().collect()
+}
 
-fn circle_circle(circle1: &Circle, circle2: &Circle) -> Option<CollisionInfo> {
-    None
+fn chain_shape<S: Scalar>(chain: &Chain<S>, shape: &Shape<S>) -> Vec<CollisionInfo<S>> {
+    // Normals point from the chain to the shape.
+    let aabb = shape.get_aabb();
+    let center = aabb.min.middle(aabb.max);
+    let mut collisions: Vec<CollisionInfo<S>> = vec![];
+    for i in 0..chain.num_segments() {
+        let segment = chain.get_segment(i);
+        if !Aabb::from_points(&[segment.start, segment.end]).overlaps(&aabb) {
+            continue
+        }
+        let segment_collisions = match *shape {
+            Shape::Polygon(ref polygon) => { flip(polygon_rounded_segment(polygon, segment.start, segment.end, S::zero())) }
+            _ => {
+                let (start, end, radius) = shape.as_rounded_segment().unwrap();
+                rounded_segment_rounded_segment(segment.start, segment.end, S::zero(), start, end, radius)
+            }
+        };
+        for collision in segment_collisions.into_iter().filter_map(|c| apply_ghost_vertices(&segment, shape, center, c)) {
+            // Neighbouring segments find the same contact at their shared vertex.
+            let is_duplicate = collisions.iter().any(|c| (c.pos - collision.pos).norm() < S::from_f64(COLLISION_MARGIN) && c.normal * collision.normal > S::from_f64(1.0 - FACE_TOLERANCE));
+            if !is_duplicate {
+                collisions.push(collision);
+            }
+        }
+    }
+    collisions
 }
 
-fn polygon_polygon(polygon1: &Polygon, polygon2: &Polygon) -> (Option<CollisionInfo>, Option<CollisionInfo>) {
-    let mut min_depth_normal: Option<(f64, Point)> = None;
-    for (i, &edge) in polygon1.get_normals().iter().chain(polygon2.get_normals().iter()).enumerate() {
-        let projection1 = polygon1.project(edge);
-        let projection2 = polygon2.project(edge);
-        let depth = get_depth_from_projections(projection1, projection2);
-        if depth < 0.0 {
-            return (None, None)
+fn apply_ghost_vertices<S: Scalar>(segment: &ChainSegment<S>, shape: &Shape<S>, center: Point<S>, collision: CollisionInfo<S>) -> Option<CollisionInfo<S>> {
+    // Contacts at the vertices of a segment are only kept if the vertex is a convex
+    // corner of the chain and the normal is not in the region of the neighbouring
+    // segment. At flat or concave corners the normal is replaced by the face
+    // normal, so that shapes slide across the joint instead of catching on it.
+    let tangent = (segment.end - segment.start).normalized();
+    let mut face_normal = tangent.orth();
+    if face_normal * (center - segment.start) < S::zero() {
+        face_normal = -face_normal;
+    }
+    if (collision.normal * tangent).abs() < S::from_f64(FACE_TOLERANCE) {
+        return Some(collision)
+    }
+    let at_start = (collision.pos - segment.start) * tangent < S::from_f64(0.5) * (segment.end - segment.start).norm();
+    let (ghost, vertex, outward) = if at_start {
+        (segment.previous, segment.start, -tangent)
+    }
+    else {
+        (segment.next, segment.end, tangent)
+    };
+    let ghost = match ghost {
+        Some(ghost) => { ghost }
+        None => { return Some(collision) }
+    };
+    let neighbour_direction = (ghost - vertex).normalized();
+    let is_convex = neighbour_direction * face_normal < S::zero();
+    if is_convex && collision.normal * outward >= S::zero() {
+        if collision.normal * neighbour_direction > S::zero() {
+            None
+        }
+        else {
+            Some(collision)
+        }
+    }
+    else {
+        let depth = segment.start * face_normal - shape.project(face_normal)[0];
+        let pos = match shape.as_rounded_segment() {
+            Some((start, end, radius)) => { closest_point_on_segment(vertex, start, end) - face_normal * (radius - depth * S::from_f64(0.5)) }
+            None => { collision.pos }
+        };
+        if depth > S::zero() {
+            Some(CollisionInfo::new(pos, depth, face_normal))
         }
         else {
-            let is_deeper = match min_depth_normal {
+            None
+        }
+    }
+}
+
+fn polygon_rounded_segment<S: Scalar>(polygon: &Polygon<S>, start: Point<S>, end: Point<S>, radius: S) -> Vec<CollisionInfo<S>> {
+    // Normals point from the polygon to the rounded segment. The skin of the
+    // polygon is added to the radius of the segment.
+    let radius = radius + polygon.radius;
+    let num_vertices = polygon.vertices.len();
+    let normals = get_outward_normals(polygon);
+    let mut max_face: Option<(S, usize, Point<S>)> = None;
+    for (i, &normal) in normals.iter().enumerate() {
+        let separation = (start * normal).min(end * normal) - polygon.vertices[i] * normal;
+        let is_larger = match max_face {
+            None => { true }
+            Some((max_separation, _, _)) => { separation > max_separation }
+        };
+        if is_larger {
+            max_face = Some((separation, i, normal));
+        }
+    }
+    let (face_separation, face_index, face_normal) = max_face.unwrap();
+    let segment_axis = if start != end {
+        let mut normal = (end - start).normalized().orth();
+        if (polygon.pos - start) * normal < S::zero() {
+            normal = -normal;
+        }
+        Some((polygon.project_core(normal)[0] - start * normal, normal))
+    }
+    else {
+        None
+    };
+    let max_separation = match segment_axis {
+        Some((separation, _)) => { face_separation.max(separation) }
+        None => { face_separation }
+    };
+    if max_separation > radius {
+        return vec![]
+    }
+    let face_start = polygon.vertices[face_index];
+    let face_end = polygon.vertices[(face_index + 1) % num_vertices];
+    if max_separation > S::zero() {
+        // The cores do not overlap, only the rounding reaches into the polygon.
+        let mut closest: Option<(Point<S>, Point<S>, S)> = None;
+        for i in 0..num_vertices {
+            let (on_segment, on_polygon) = closest_points_segments(start, end, polygon.vertices[i], polygon.vertices[(i + 1) % num_vertices]);
+            let distance = (on_segment - on_polygon).norm();
+            let is_closer = match closest {
                 None => { true }
-                Some((min_depth, _)) => { depth < min_depth }
+                Some((_, _, min_distance)) => { distance < min_distance }
             };
-            if is_deeper {
-                let normal = edge;
-                let corrected_normal = match (polygon1.pos - polygon2.pos) * normal < 0.0 {
-                    true => { normal.clone() }
-                    false => { -normal.clone() }
-                };
-                min_depth_normal = Some((depth, corrected_normal));
+            if is_closer {
+                closest = Some((on_segment, on_polygon, distance));
+            }
+        }
+        let (on_segment, on_polygon, distance) = closest.unwrap();
+        if distance >= radius {
+            return vec![]
+        }
+        if start != end && is_parallel(end - start, face_end - face_start) && face_separation > S::zero() {
+            let collisions = clip_against_face(start, end, radius, face_start, face_end, face_normal);
+            if !collisions.is_empty() {
+                return collisions
             }
         }
+        let normal = (on_segment - on_polygon) / distance;
+        let depth = radius - distance;
+        return vec![CollisionInfo::new(on_polygon + normal * depth * S::from_f64(0.5), depth, normal)]
     }
-    let collision_pos = get_collision_pos(polygon1, polygon2, min_depth_normal.unwrap().1);
-    let create_collision_info = |pos| CollisionInfo {
-        depth: min_depth_normal.unwrap().0,
-        normal: min_depth_normal.unwrap().1,
-        pos: pos
-    };
-    (Some(create_collision_info(collision_pos.0)), collision_pos.1.map(create_collision_info))
-}
-
-fn get_closest_points(polygon1: &Polygon, polygon2: &Polygon, normal: Point) -> (Point, Option<Point>){
-    // Find the point of polygon1 that is closest to polygon2 (along the normal).
-    // Also return the second closest point if the distance of the second closest 
-    // point is almost as small as the closest point
-    let mut closest: Option<(Point, f64)> = None;
-    let mut second_closest: Option<(Point, f64)> = None;
-    let comparison = polygon2.pos * normal;
-    for &point in polygon1.vertices.iter() {
-        let distance = (point * normal - comparison).abs();
-        match closest {
-            None => { 
-                closest = Some((point, distance)); continue 
-            },
-            Some((_closest_point, closest_distance)) => {
-                if distance + super::COLLISION_MARGIN < closest_distance {
-                    second_closest = None;
-                    closest = Some((point, distance));
-                }
-                else if distance < closest_distance {
-                    second_closest = closest;
-                    closest = Some((point, distance));
+    match segment_axis {
+        Some((separation, normal)) if separation > face_separation + S::from_f64(AXIS_TOLERANCE) => {
+            // The segment is the reference face, the incident edge is the edge of
+            // the polygon that points against the normal of the segment the most.
+            let incident_index = (0..num_vertices).min_by(|&i, &j| (normals[i] * normal).partial_cmp(&(normals[j] * normal)).unwrap()).unwrap();
+            let incident_start = polygon.vertices[incident_index];
+            let incident_end = polygon.vertices[(incident_index + 1) % num_vertices];
+            let axis = (end - start).normalized();
+            let clipped = clip_to_interval(incident_start, incident_end, axis, (start * axis).min(end * axis), (start * axis).max(end * axis));
+            clipped.iter().filter_map(|&point| {
+                let depth = (start * normal + radius) - point * normal;
+                if depth > S::zero() {
+                    Some(CollisionInfo::new(point + normal * depth * S::from_f64(0.5), depth, -normal))
                 }
-                else if distance < closest_distance + super::COLLISION_MARGIN {
-                    second_closest = Some((point, distance));
+                else {
+                    None
                 }
+            }).collect()
+        }
+        _ => {
+            let collisions = clip_against_face(start, end, radius, face_start, face_end, face_normal);
+            if !collisions.is_empty() {
+                return collisions
             }
+            // None of the segment lies in front of the face, use the deepest end point instead.
+            let deepest = if start * face_normal < end * face_normal { start } else { end };
+            let depth = radius - (deepest - face_start) * face_normal;
+            vec![CollisionInfo::new(deepest - face_normal * (radius - depth * S::from_f64(0.5)), depth, face_normal)]
+        }
+    }
+}
+
+fn get_outward_normals<S: Scalar>(polygon: &Polygon<S>) -> Vec<Point<S>> {
+    // get_normals only points outwards for polygons with counterclockwise vertices.
+    polygon.get_normals().iter().enumerate().map(|(i, &normal)| {
+        if (polygon.vertices[i] - polygon.pos) * normal < S::zero() { -normal } else { normal }
+    }).collect()
+}
+
+fn clip_against_face<S: Scalar>(start: Point<S>, end: Point<S>, radius: S, face_start: Point<S>, face_end: Point<S>, face_normal: Point<S>) -> Vec<CollisionInfo<S>> {
+    // Clip the segment to the side planes of the face and keep the points whose rounding reaches behind the face.
+    let axis = (face_end - face_start).normalized();
+    let clipped = clip_to_interval(start, end, axis, (face_start * axis).min(face_end * axis), (face_start * axis).max(face_end * axis));
+    clipped.iter().filter_map(|&point| {
+        let depth = radius - (point - face_start) * face_normal;
+        if depth > S::zero() {
+            Some(CollisionInfo::new(point - face_normal * (radius - depth * S::from_f64(0.5)), depth, face_normal))
+        }
+        else {
+            None
         }
+    }).collect()
+}
 
+fn polygon_polygon<S: Scalar>(polygon1: &Polygon<S>, polygon2: &Polygon<S>) -> Vec<CollisionInfo<S>> {
+    // Normals point from polygon1 to polygon2. The face of least penetration is the
+    // reference face, the edge of the other polygon that points against it the most
+    // is the incident edge. The incident edge is clipped to the side planes of the
+    // reference face, which gives up to two contact points with their own depth.
+    // Returns no collisions if the cores do not overlap.
+    let (separation1, face1) = max_separation(polygon1, polygon2);
+    let (separation2, face2) = max_separation(polygon2, polygon1);
+    if separation1 > S::zero() || separation2 > S::zero() {
+        return vec![]
     }
-    (closest.unwrap().0, second_closest.map(|x| x.0))
+    let (reference, incident, face, flipped) = if separation2 > separation1 + S::from_f64(AXIS_TOLERANCE) {
+        (polygon2, polygon1, face2, true)
+    }
+    else {
+        (polygon1, polygon2, face1, false)
+    };
+    let reference_normals = get_outward_normals(reference);
+    let incident_normals = get_outward_normals(incident);
+    let normal = reference_normals[face];
+    let face_start = reference.vertices[face];
+    let face_end = reference.vertices[(face + 1) % reference.vertices.len()];
+    let incident_index = (0..incident.vertices.len()).min_by(|&i, &j| (incident_normals[i] * normal).partial_cmp(&(incident_normals[j] * normal)).unwrap()).unwrap();
+    let incident_start = incident.vertices[incident_index];
+    let incident_end = incident.vertices[(incident_index + 1) % incident.vertices.len()];
+    let axis = (face_end - face_start).normalized();
+    let clipped = clip_to_interval(incident_start, incident_end, axis, (face_start * axis).min(face_end * axis), (face_start * axis).max(face_end * axis));
+    let radius = reference.radius + incident.radius;
+    clipped.iter().filter_map(|&point| {
+        let separation = (point - face_start) * normal;
+        let depth = radius - separation;
+        if depth > S::zero() {
+            // Halfway between the surfaces of the reference face and the incident edge.
+            let pos = point + normal * ((reference.radius - incident.radius - separation) * S::from_f64(0.5));
+            Some(CollisionInfo::new(pos, depth, if flipped { -normal } else { normal }))
+        }
+        else {
+            None
+        }
+    }).collect()
 }
 
-fn get_collision_pos(polygon1: &Polygon, polygon2: &Polygon, normal: Point) -> (Point, Option<Point>) {
-    let (closest1, second_closest_1) = get_closest_points(polygon1, polygon2, normal);
-    let (closest2, second_closest_2) = get_closest_points(polygon2, polygon1, normal);
-    match second_closest_1 {
-        None => {
-            match second_closest_2 {
-                // Point-Point: Return the middle
-                None => ((closest1 + closest2) * 0.5, None),
-                // Point-Line: Return the point
-                Some(_) => (closest1, None)
-            }
-        },
-        Some(second_closest_point_1) => {
-            match second_closest_2 {
-                // Line-Point: Return the point
-                None => (closest2, None),
-                // Line-Line: Find the proper manifold between the four points.
-                Some(second_closest_point_2) => {
-                    let (point1, point2) = find_manifold(closest1, second_closest_point_1, closest2, second_closest_point_2, normal);
-                    (point1, Some(point2))
+fn max_separation<S: Scalar>(polygon1: &Polygon<S>, polygon2: &Polygon<S>) -> (S, usize) {
+    // The face of polygon1 along which the core of polygon2 is separated the most
+    // (or penetrates the least) and the signed separation.
+    let mut max: Option<(S, usize)> = None;
+    for (i, &normal) in get_outward_normals(polygon1).iter().enumerate() {
+        let separation = polygon2.project_core(normal)[0] - polygon1.vertices[i] * normal;
+        let is_larger = match max {
+            None => { true }
+            Some((max_separation, _)) => { separation > max_separation }
+        };
+        if is_larger {
+            max = Some((separation, i));
+        }
+    }
+    max.unwrap()
+}
+
+type Edge<S> = (Point<S>, Point<S>);
 
+fn rounded_polygon_polygon<S: Scalar>(polygon1: &Polygon<S>, polygon2: &Polygon<S>) -> Vec<CollisionInfo<S>> {
+    // The cores are separated, so only the skins can touch. The contact is found
+    // between the closest pair of edges, preferring edges that face each other on
+    // ties so that faces lying on each other get two contact points.
+    let edge = |polygon: &Polygon<S>, i: usize| (polygon.vertices[i], polygon.vertices[(i + 1) % polygon.vertices.len()]);
+    let mut closest: Option<(S, bool, Edge<S>, Edge<S>)> = None;
+    for i in 0..polygon1.vertices.len() {
+        let edge1 = edge(polygon1, i);
+        for j in 0..polygon2.vertices.len() {
+            let edge2 = edge(polygon2, j);
+            let (closest1, closest2) = closest_points_segments(edge1.0, edge1.1, edge2.0, edge2.1);
+            let distance = (closest2 - closest1).norm();
+            let is_facing = is_parallel(edge1.1 - edge1.0, edge2.1 - edge2.0) && distance > S::zero() && is_parallel(edge1.1 - edge1.0, (closest2 - closest1).orth());
+            let is_closer = match closest {
+                None => { true }
+                Some((min_distance, min_is_facing, _, _)) => {
+                    if (distance - min_distance).abs() < S::from_f64(DISTANCE_TOLERANCE) { is_facing && !min_is_facing } else { distance < min_distance }
                 }
+            };
+            if is_closer {
+                closest = Some((distance, is_facing, edge1, edge2));
             }
         }
     }
-}
-
-fn find_manifold(p1: Point, q1: Point, p2: Point, q2: Point, normal: Point) -> (Point, Point) {
-    // Determine the contact line between the four points that make up two touching
-    // lines.
-    // Four cases to consider
-    // 1)
-    // p1 |        | q1
-    // p2    |  |    q2
-    //       ____
-    // 2)
-    // p1    |     | q1
-    // p2 |     |    q2
-    //       ____
-    // 3)
-    // p1 |     |    q1
-    // p2    |     | q2
-    //       ____
-    // 4)
-    // p1    |  |    q1
-    // p2 |        | q2
-    //       ____
-    let line = normal.orth();
-    let p1_projected = p1 * line;
-    let q1_projected = q1 * line;
-    let p2_projected = p2 * line;
-    let q2_projected = q2 * line;
-    let min1 = p1_projected.min(q1_projected);
-    let min2 = p2_projected.min(q2_projected);
-    let max1 = p1_projected.max(q1_projected);
-    let max2 = p2_projected.max(q2_projected);
-    let start_manifold_projected = min1.max(min2);
-    let end_manifold_projected = max1.min(max2);
-    // All of these points lie on a line (approximately). This line is parametrized by 
-    // p + t * line where p is any of the four points. Therefore we may choose any of them.
-    // Here we choose p1.
-    let start_manifold = p1 + line * (start_manifold_projected - p1_projected);
-    let end_manifold = p1 + line * (end_manifold_projected - p1_projected);
-    (start_manifold, end_manifold)
-}
-
-fn get_depth_from_projections(projection1: [f64; 2], projection2: [f64; 2]) -> f64 {
-    return (projection1[1] - projection2[0]).min(projection2[1] - projection1[0]);
+    let (_, _, edge1, edge2) = closest.unwrap();
+    rounded_segment_rounded_segment(edge1.0, edge1.1, polygon1.radius, edge2.0, edge2.1, polygon2.radius)
 }
 
 #[cfg(test)]
 mod tests{
     use super::*;
-    #[test] 
-    fn test_closest_points() {
-        let mut vertices = vec![];
-        vertices.push(Point::new(0.0, 0.0));
-        vertices.push(Point::new(1.0, 0.0));
-        vertices.push(Point::new(1.0, 1.0));
-        vertices.push(Point::new(0.0, 1.0));
-        let poly1 = Polygon::new(vertices);
-        vertices = vec![];
-        vertices.push(Point::new(2.0, 0.0));
-        vertices.push(Point::new(3.0, 0.0));
-        vertices.push(Point::new(3.0, 1.0));
-        vertices.push(Point::new(2.0, 1.0));
-        let poly2 = Polygon::new(vertices);
-        let points = get_closest_points(&poly1, &poly2, Point::new(1.0, 0.0));
-        let is_close = |p1: Point, p2: Point| (p1-p2).norm() < 0.0001;
-        assert!(is_close(points.0, Point::new(1.0, 0.0)));
-        assert!(is_close(points.1.unwrap(), Point::new(1.0, 1.0)));
-
-        vertices = vec![];
-        vertices.push(Point::new(0.0, 0.0));
-        vertices.push(Point::new(1.5, 0.0));
-        vertices.push(Point::new(1.0, 1.0));
-        vertices.push(Point::new(0.0, 1.0));
-        let poly1 = Polygon::new(vertices);
-        let points = get_closest_points(&poly1, &poly2, Point::new(1.0, 0.0));
-        let is_close = |p1: Point, p2: Point| (p1-p2).norm() < 0.0001;
-        assert!(is_close(points.0, Point::new(1.5, 0.0)));
-        assert!(match points.1 { None => true, _ => false });
+    use simulation::circle::Circle;
+
+    #[test]
+    fn test_distance_to_concave_shapes() {
+        // The circle sits in the notch of an L shape, which is inside the convex hull.
+        let l_shape = Shape::Compound(Compound::new(vec![
+            Polygon::new(vec![Point::new(0.0, 0.0), Point::new(2.0, 0.0), Point::new(2.0, 1.0), Point::new(0.0, 1.0)]),
+            Polygon::new(vec![Point::new(0.0, 1.0), Point::new(1.0, 1.0), Point::new(1.0, 2.0), Point::new(0.0, 2.0)])
+        ]));
+        let circle = Shape::Circle(Circle::new(Point::new(1.75, 1.75), 0.1));
+        let result = shape_distance(&l_shape, &circle);
+        assert!((result.distance - 0.65).abs() < 0.00001);
+        assert!((shape_distance(&circle, &l_shape).point2 - result.point1).norm() < 0.00001);
+        let chain = Shape::Chain(Chain::new(vec![Point::new(0.0, 0.0), Point::new(1.0, 2.0), Point::new(2.0, 0.0)], false).unwrap());
+        let circle = Shape::Circle(Circle::new(Point::new(1.0, 0.5), 0.1));
+        assert!((shape_distance(&chain, &circle).distance - (1.5 / 5.0f64.sqrt() - 0.1)).abs() < 0.00001);
+    }
+
+    #[test]
+    fn test_segment_segment() {
+        // The vertical segment pokes 0.1 through the horizontal one.
+        let (start1, end1) = (Point::new(-2.0, 0.0), Point::new(2.0, 0.0));
+        let (start2, end2) = (Point::new(0.5, -1.0), Point::new(0.5, 0.1));
+        let collisions = rounded_segment_rounded_segment(start1, end1, 0.0, start2, end2, 0.0);
+        assert_eq!(collisions.len(), 1);
+        assert!((collisions[0].depth - 0.1).abs() < 0.00001);
+        assert!((collisions[0].normal - Point::new(0.0, -1.0)).norm() < 0.00001);
+        assert!((collisions[0].pos - Point::new(0.5, 0.05)).norm() < 0.00001);
+        let collisions = rounded_segment_rounded_segment(start2, end2, 0.0, start1, end1, 0.0);
+        assert!((collisions[0].normal - Point::new(0.0, 1.0)).norm() < 0.00001);
+        assert!(rounded_segment_rounded_segment(start1, end1, 0.0, start2 + Point::new(3.0, 0.0), end2 + Point::new(3.0, 0.0), 0.0).is_empty());
+    }
+    #[test]
+    fn test_rotated_box_on_box() {
+        let vertices = vec![
+            Point::new(-2.0, 0.0),
+            Point::new(2.0, 0.0),
+            Point::new(2.0, 1.0),
+            Point::new(-2.0, 1.0)
+        ];
+        let ground = Polygon::new(vertices);
+        // A unit box tilted so that its right corner is 0.1 deeper than its left one.
+        let c = (1.0 - 0.1f64 * 0.1).sqrt();
+        let corner = Point::new(-0.5, 0.0);
+        let tilted = Polygon::new(vec![corner, corner + Point::new(c, 0.1), corner + Point::new(c + 0.1, 0.1 - c), corner + Point::new(0.1, -c)]);
+        let collisions = polygon_polygon(&ground, &tilted);
+        assert_eq!(collisions.len(), 1);
+        assert!((collisions[0].depth - 0.1).abs() < 0.00001);
+        assert!((collisions[0].normal - Point::new(0.0, -1.0)).norm() < 0.00001);
+        let flat = Polygon::new(vec![Point::new(-0.5, 0.05), Point::new(0.5, 0.05), Point::new(0.5, -0.95), Point::new(-0.5, -0.95)]);
+        let collisions = polygon_polygon(&ground, &flat);
+        assert_eq!(collisions.len(), 2);
+        for collision in collisions.iter() {
+            assert!((collision.depth - 0.05).abs() < 0.00001);
+            assert!((collision.normal - Point::new(0.0, -1.0)).norm() < 0.00001);
+        }
+        let collisions = polygon_polygon(&flat, &ground);
+        assert_eq!(collisions.len(), 2);
+        assert!((collisions[0].normal - Point::new(0.0, 1.0)).norm() < 0.00001);
+    }
+
+    #[test]
+    fn test_capsule_on_box() {
+        let vertices = vec![
+            Point::new(-2.0, 0.0),
+            Point::new(2.0, 0.0),
+            Point::new(2.0, 1.0),
+            Point::new(-2.0, 1.0)
+        ];
+        let poly = Polygon::new(vertices);
+        let collisions = polygon_rounded_segment(&poly, Point::new(-1.0, -0.4), Point::new(1.0, -0.4), 0.5);
+        assert_eq!(collisions.len(), 2);
+        for collision in collisions.iter() {
+            assert!((collision.depth - 0.1).abs() < 0.00001);
+            assert!((collision.normal - Point::new(0.0, -1.0)).norm() < 0.00001);
+        }
+        let collisions = polygon_rounded_segment(&poly, Point::new(2.24, -0.32), Point::new(2.24, -0.32), 0.5);
+        assert_eq!(collisions.len(), 1);
+        assert!((collisions[0].normal - Point::new(0.6, -0.8)).norm() < 0.00001);
+        assert!((collisions[0].depth - 0.1).abs() < 0.00001);
+        assert!(polygon_rounded_segment(&poly, Point::new(2.4, -0.4), Point::new(2.4, -0.4), 0.5).is_empty());
+    }
+
+    #[test]
+    fn test_chain_joint_does_not_catch() {
+        let chain = Chain::new(vec![Point::new(-2.0, 0.0), Point::new(-1.0, 0.0), Point::new(0.0, 0.0), Point::new(1.0, 0.0)], false).unwrap();
+        let vertices = vec![
+            Point::new(-0.99, -1.0),
+            Point::new(0.01, -1.0),
+            Point::new(0.01, 0.02),
+            Point::new(-0.99, 0.02)
+        ];
+        let shape = Shape::Polygon(Polygon::new(vertices));
+        let collisions = chain_shape(&chain, &shape);
+        assert!(!collisions.is_empty());
+        for collision in collisions.iter() {
+            assert!((collision.normal - Point::new(0.0, -1.0)).norm() < 0.00001);
+            assert!((collision.depth - 0.02).abs() < 0.00001);
+        }
+    }
+
+    #[test]
+    fn test_rounded_polygons_with_separated_cores() {
+        let square = |x: f64| vec![Point::new(x, 0.0), Point::new(x + 1.0, 0.0), Point::new(x + 1.0, 1.0), Point::new(x, 1.0)];
+        let poly1 = Polygon::with_radius(square(0.0), 0.1);
+        let poly2 = Polygon::with_radius(square(1.1), 0.1);
+        let body1 = Body::new(poly1.pos, 1.0, Shape::Polygon(poly1), false);
+        let body2 = Body::new(poly2.pos, 1.0, Shape::Polygon(poly2), false);
+        let collisions = find_collisions(&body1, &body2);
+        assert_eq!(collisions.len(), 2);
+        for collision in collisions.iter() {
+            assert!((collision.depth - 0.1).abs() < 0.00001);
+            assert!((collision.normal - Point::new(1.0, 0.0)).norm() < 0.00001);
+        }
+    }
+
+    #[test]
+    fn test_circle_circle() {
+        let collisions = rounded_segment_rounded_segment(Point::new(0.0, 0.0), Point::new(0.0, 0.0), 1.0, Point::new(1.5, 0.0), Point::new(1.5, 0.0), 1.0);
+        assert_eq!(collisions.len(), 1);
+        assert!((collisions[0].depth - 0.5).abs() < 0.00001);
+        assert!((collisions[0].normal - Point::new(1.0, 0.0)).norm() < 0.00001);
+        assert!((collisions[0].pos - Point::new(0.75, 0.0)).norm() < 0.00001);
     }
 }
@@ -1,133 +1,544 @@
 use simulation::body::Body;
-use simulation::Simulation;
 use simulation::collision_detection;
+use simulation::events::Event;
+use simulation::joint::{RevoluteJoint, JointConstraint};
+
+use std::collections::BTreeSet;
 
 use point::Point;
-use super::{BAUMGARTE_FACTOR, ALLOWED_PENETRATION, NUM_ITERATIONS, FRICTION};
+use scalar::Scalar;
+#[cfg(feature = "parallel")]
+use simulation::parallel;
+use super::{DT, BAUMGARTE_FACTOR, ALLOWED_PENETRATION, NUM_ITERATIONS, FRICTION, RESTITUTION, RESTITUTION_THRESHOLD, ONE_WAY_VELOCITY_TOLERANCE};
+
+pub type PreSolve<S = f64> = dyn FnMut(&Body<S>, &Body<S>, &mut Collision<S>);
 
-// https://stackoverflow.com/questions/30073684/how-to-get-mutable-references-to-two-array-elements-at-the-same-time/30075629
-enum Pair<T> {
-    Both(T, T),
-    One(T),
-    None,
+pub struct CollisionHandler<S = f64> {
+    pub collisions: Vec<Collision<S>>,
+    sensor_overlaps: BTreeSet<(usize, usize)>,
+    contacts: BTreeSet<(usize, usize)>,
+    one_way_passing: BTreeSet<(usize, usize)>,
+    pre_solve: Option<Box<PreSolve<S>>>,
+    // The number of pairs that passed the broadphase in the last step.
+    num_pairs: usize,
+    // The number of contact and joint constraints resolved in the last step,
+    // counted once per iteration of the solver.
+    num_constraint_solves: usize,
+    #[cfg(feature = "parallel")]
+    num_threads: usize
 }
 
-impl<T> Pair<T> {
-    fn unwrap(self) -> (T, T){
-        match self {
-            Pair::Both(a, b) => (a, b),
-            _ => panic!("Pair unwrap - invalid indices")
-        }
+// The state of a collision handler that carries over from one step to the next.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CollisionCache<S = f64> {
+    collisions: Vec<Collision<S>>,
+    sensor_overlaps: BTreeSet<(usize, usize)>,
+    contacts: BTreeSet<(usize, usize)>,
+    one_way_passing: BTreeSet<(usize, usize)>
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CollisionInfo<S = f64> {
+    pub pos: Point<S>,
+    pub depth: S,
+    pub normal: Point<S>,
+    // The surface velocities of the two touching convex shapes.
+    pub surface_velocity1: S,
+    pub surface_velocity2: S
+}
+
+impl<S: Scalar> CollisionInfo<S> {
+    pub fn new(pos: Point<S>, depth: S, normal: Point<S>) -> CollisionInfo<S> {
+        CollisionInfo { pos, depth, normal, surface_velocity1: S::zero(), surface_velocity2: S::zero() }
     }
 }
 
-fn index_twice<T>(slc: &mut [T], a: usize, b: usize) -> Pair<&mut T> {
-    if a == b {
-        slc.get_mut(a).map_or(Pair::None, Pair::One)
-    } else {
-        if a >= slc.len() || b >= slc.len() {
-            Pair::None
-        } else {
-            // safe because a, b are in bounds and distinct
-            unsafe {
-                let ar = &mut *(slc.get_unchecked_mut(a) as *mut _);
-                let br = &mut *(slc.get_unchecked_mut(b) as *mut _);
-                Pair::Both(ar, br)
-            }
+#[derive(Debug, Clone, PartialEq)]
+pub struct Collision<S = f64> {
+    pub info: CollisionInfo<S>,
+    pub body1: usize,
+    pub body2: usize,
+    pub friction: S,
+    pub restitution: S,
+    pub enabled: bool,
+    pub normal_impulse: S,
+    pub tangent_impulse: S
+}
+
+impl<S: Scalar> Collision<S> {
+    pub fn new(info: CollisionInfo<S>, body1: usize, body2: usize) -> Collision<S> {
+        Collision {
+            info,
+            body1,
+            body2,
+            friction: S::from_f64(FRICTION),
+            restitution: S::from_f64(RESTITUTION),
+            enabled: true,
+            normal_impulse: S::zero(),
+            tangent_impulse: S::zero()
         }
     }
 }
 
-pub struct CollisionHandler {
-    pub collisions: Vec<Collision>
+// A collision prepared for the solver. Everything but the accumulated impulses
+// stays the same during the iterations, so that the solver only needs the
+// velocities of the bodies.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Constraint<S = f64> {
+    pub collision: usize,
+    pub body1: usize,
+    pub body2: usize,
+    pub r1: Point<S>,
+    pub r2: Point<S>,
+    pub normal: Point<S>,
+    pub tangent: Point<S>,
+    pub bias: S,
+    pub k_normal: S,
+    pub k_tangent: S,
+    pub friction: S,
+    pub surface_velocity1: S,
+    pub surface_velocity2: S,
+    pub mass1: S,
+    pub mass2: S,
+    pub inertia1: S,
+    pub inertia2: S,
+    pub is_static1: bool,
+    pub is_static2: bool,
+    pub normal_impulse: S,
+    pub tangent_impulse: S
 }
 
-#[derive(Debug, Clone, Copy)]
-pub struct CollisionInfo {
-    pub pos: Point,
-    pub depth: f64,
-    pub normal: Point,
+// Access to the velocities of the bodies while resolving constraints.
+pub trait Velocities<S> {
+    fn vel_at(&self, body: usize, relative_pos: Point<S>) -> Point<S>;
+    fn add_vel(&mut self, body: usize, vel: Point<S>, avel: S);
 }
 
-#[derive(Debug)]
-pub struct Collision {
-    pub info: CollisionInfo,
+#[derive(Debug, Clone, PartialEq)]
+pub struct Contact<S = f64> {
     pub body1: usize,
-    pub body2: usize
+    pub body2: usize,
+    pub points: Vec<Point<S>>,
+    pub normal: Point<S>,
+    pub normal_impulse: S,
+    pub tangent_impulse: S
+}
+
+impl<S: Scalar> Default for CollisionHandler<S> {
+    fn default() -> CollisionHandler<S> {
+        CollisionHandler::new()
+    }
 }
 
-impl CollisionHandler{
-    pub fn new() -> CollisionHandler {
+impl<S: Scalar> CollisionHandler<S> {
+    pub fn new() -> CollisionHandler<S> {
         CollisionHandler {
-            collisions: vec![]
+            collisions: vec![],
+            sensor_overlaps: BTreeSet::new(),
+            contacts: BTreeSet::new(),
+            one_way_passing: BTreeSet::new(),
+            pre_solve: None,
+            num_pairs: 0,
+            num_constraint_solves: 0,
+            #[cfg(feature = "parallel")]
+            num_threads: 1
+        }
+    }
+
+    // With more than one thread the narrowphase runs on worker threads and the
+    // solver resolves batches of collisions that share no dynamic body at the same
+    // time. The batches visit the collisions in a different order than the single
+    // threaded solver, so the results differ from it, but not between different
+    // numbers of threads.
+    #[cfg(feature = "parallel")]
+    pub fn set_num_threads(&mut self, num_threads: usize) {
+        self.num_threads = num_threads.max(1);
+    }
+
+    #[cfg(feature = "parallel")]
+    pub fn get_num_threads(&self) -> usize {
+        self.num_threads
+    }
+
+    pub fn get_cache(&self) -> CollisionCache<S> {
+        CollisionCache {
+            collisions: self.collisions.clone(),
+            sensor_overlaps: self.sensor_overlaps.clone(),
+            contacts: self.contacts.clone(),
+            one_way_passing: self.one_way_passing.clone()
+        }
+    }
+
+    // Restores the state saved by get_cache. The pre-solve hook is not part of it.
+    pub fn set_cache(&mut self, cache: &CollisionCache<S>) {
+        self.collisions = cache.collisions.clone();
+        self.sensor_overlaps = cache.sensor_overlaps.clone();
+        self.contacts = cache.contacts.clone();
+        self.one_way_passing = cache.one_way_passing.clone();
+    }
+
+    // The pre-solve hook is called once per collision and step before any impulses
+    // are applied. It may disable the collision or change its friction and restitution.
+    pub fn set_pre_solve<F>(&mut self, pre_solve: F) where F: FnMut(&Body<S>, &Body<S>, &mut Collision<S>) + 'static {
+        self.pre_solve = Some(Box::new(pre_solve));
+    }
+
+    pub fn clear_pre_solve(&mut self) {
+        self.pre_solve = None;
+    }
+
+    // Forget all pairs with the body, for example because it was replaced by
+    // another one. No events are emitted for the forgotten pairs.
+    pub fn forget_body(&mut self, index: usize) {
+        let is_other_pair = |&(body1, body2): &(usize, usize)| body1 != index && body2 != index;
+        self.sensor_overlaps.retain(is_other_pair);
+        self.contacts.retain(is_other_pair);
+        self.one_way_passing.retain(is_other_pair);
+        self.collisions.retain(|collision| collision.body1 != index && collision.body2 != index);
+    }
+
+    // Forget all pairs with the removed body and move the indices of the bodies
+    // after it down by one. No events are emitted for the forgotten pairs.
+    pub fn remove_body(&mut self, index: usize) {
+        self.forget_body(index);
+        let shift = |body: usize| if body > index { body - 1 } else { body };
+        let remap = |pairs: &BTreeSet<(usize, usize)>| -> BTreeSet<(usize, usize)> {
+            pairs.iter().map(|&(body1, body2)| (shift(body1), shift(body2))).collect()
+        };
+        self.sensor_overlaps = remap(&self.sensor_overlaps);
+        self.contacts = remap(&self.contacts);
+        self.one_way_passing = remap(&self.one_way_passing);
+        for collision in self.collisions.iter_mut() {
+            collision.body1 = shift(collision.body1);
+            collision.body2 = shift(collision.body2);
+        }
+    }
+
+    // The joints are resolved together with the collisions.
+    pub fn timestep(&mut self, bodies: &mut [Body<S>], joints: &[RevoluteJoint<S>], events: &mut Vec<Event<S>>) {
+        let sensor_overlaps = self.find_collisions(bodies);
+        self.update_sensor_overlaps(sensor_overlaps, events);
+        self.handle_one_way_platforms(bodies);
+        self.run_pre_solve(bodies);
+        let mut constraints = self.get_constraints(bodies);
+        let mut joint_constraints: Vec<JointConstraint<S>> = joints.iter().filter_map(|joint| JointConstraint::new(joint, bodies)).collect();
+        self.num_constraint_solves = NUM_ITERATIONS * (constraints.len() + joint_constraints.len());
+        self.resolve_constraints(&mut constraints, &mut joint_constraints, bodies);
+        for constraint in constraints.iter() {
+            let collision = &mut self.collisions[constraint.collision];
+            collision.normal_impulse = constraint.normal_impulse;
+            collision.tangent_impulse = constraint.tangent_impulse;
+        }
+        self.update_contacts(events);
+    }
+
+    pub fn get_constraints(&self, bodies: &[Body<S>]) -> Vec<Constraint<S>> {
+        self.collisions.iter().enumerate()
+            .filter(|&(_, collision)| collision.enabled)
+            .map(|(index, collision)| Constraint::new(index, collision, &bodies[collision.body1], &bodies[collision.body2]))
+            .collect()
+    }
+
+    fn handle_one_way_platforms(&mut self, bodies: &[Body<S>]) {
+        // Whether a pair passes through a one way platform is only decided when the
+        // bodies start touching, and kept until they do not touch anymore. Bodies
+        // which enter the platform from the wrong side are not pushed out of it
+        // halfway through, and bodies resting on it do not fall through when they
+        // are pushed away from it.
+        let mut passing = BTreeSet::new();
+        for collision in self.collisions.iter() {
+            let pair = (collision.body1, collision.body2);
+            let is_passing = if self.contacts.contains(&pair) {
+                self.one_way_passing.contains(&pair)
+            }
+            else {
+                !one_way_allows_collision(bodies, collision)
+            };
+            if is_passing {
+                passing.insert(pair);
+            }
+        }
+        for collision in self.collisions.iter_mut() {
+            if passing.contains(&(collision.body1, collision.body2)) {
+                collision.enabled = false;
+            }
         }
+        self.one_way_passing = passing;
     }
 
-    pub fn timestep(&mut self, bodies: &mut Vec<Body>) {
-        self.find_collisions(bodies);
-        for _ in 0..NUM_ITERATIONS {
-            self.resolve_collisions(bodies);
+    fn run_pre_solve(&mut self, bodies: &[Body<S>]) {
+        if let Some(ref mut pre_solve) = self.pre_solve {
+            for collision in self.collisions.iter_mut() {
+                pre_solve(&bodies[collision.body1], &bodies[collision.body2], collision);
+            }
+        }
+    }
+
+    #[cfg(feature = "parallel")]
+    fn resolve_constraints(&self, constraints: &mut [Constraint<S>], joints: &mut [JointConstraint<S>], bodies: &mut [Body<S>]) {
+        if self.num_threads > 1 {
+            parallel::resolve_constraints(constraints, joints, bodies, self.num_threads);
+        }
+        else {
+            resolve_constraints(constraints, joints, bodies);
         }
     }
 
-    fn resolve_collisions(&self, bodies: &mut Vec<Body>) {
+    #[cfg(not(feature = "parallel"))]
+    fn resolve_constraints(&self, constraints: &mut [Constraint<S>], joints: &mut [JointConstraint<S>], bodies: &mut [Body<S>]) {
+        resolve_constraints(constraints, joints, bodies);
+    }
+
+    pub fn get_num_pairs(&self) -> usize {
+        self.num_pairs
+    }
+
+    pub fn get_num_constraint_solves(&self) -> usize {
+        self.num_constraint_solves
+    }
+
+    #[cfg(feature = "parallel")]
+    fn narrowphase(&self, bodies: &[Body<S>], pairs: &[(usize, usize)]) -> Vec<Vec<CollisionInfo<S>>> {
+        parallel::map(pairs, self.num_threads, |&(i, j)| collision_detection::find_collisions(&bodies[i], &bodies[j]))
+    }
+
+    #[cfg(not(feature = "parallel"))]
+    fn narrowphase(&self, bodies: &[Body<S>], pairs: &[(usize, usize)]) -> Vec<Vec<CollisionInfo<S>>> {
+        pairs.iter().map(|&(i, j)| collision_detection::find_collisions(&bodies[i], &bodies[j])).collect()
+    }
+
+    pub fn get_contacts(&self) -> Vec<Contact<S>> {
+        // Merge the collisions of each pair of bodies into a single contact.
+        // find_collisions stores the collisions of a pair next to each other.
+        let mut contacts: Vec<Contact<S>> = vec![];
         for collision in self.collisions.iter() {
-            let (b1, b2) = index_twice(bodies, collision.body1, collision.body2).unwrap();
-            resolve_collision(b1, b2, collision.info);
+            let impulse = if collision.enabled { (collision.normal_impulse, collision.tangent_impulse) } else { (S::zero(), S::zero()) };
+            let is_same_pair = match contacts.last() {
+                Some(contact) => { contact.body1 == collision.body1 && contact.body2 == collision.body2 }
+                None => { false }
+            };
+            if is_same_pair {
+                let contact = contacts.last_mut().unwrap();
+                contact.points.push(collision.info.pos);
+                contact.normal_impulse += impulse.0;
+                contact.tangent_impulse += impulse.1;
+            }
+            else {
+                contacts.push(Contact {
+                    body1: collision.body1,
+                    body2: collision.body2,
+                    points: vec![collision.info.pos],
+                    normal: collision.info.normal,
+                    normal_impulse: impulse.0,
+                    tangent_impulse: impulse.1
+                });
+            }
+        }
+        contacts
+    }
+
+    fn update_contacts(&mut self, events: &mut Vec<Event<S>>) {
+        let contacts = self.get_contacts();
+        let pairs: BTreeSet<(usize, usize)> = contacts.iter().map(|c| (c.body1, c.body2)).collect();
+        for contact in contacts.into_iter() {
+            if self.contacts.contains(&(contact.body1, contact.body2)) {
+                events.push(Event::ContactPersist(contact));
+            }
+            else {
+                events.push(Event::ContactBegin(contact));
+            }
+        }
+        for &(body1, body2) in self.contacts.difference(&pairs) {
+            events.push(Event::ContactEnd { body1, body2 });
         }
+        self.contacts = pairs;
     }
 
-    pub fn find_collisions(&mut self, bodies: &mut Vec<Body>) {
+    pub fn find_collisions(&mut self, bodies: &mut [Body<S>]) -> BTreeSet<(usize, usize)> {
+        // Returns the pairs of overlapping bodies in which one of the bodies is a sensor.
+        // These do not produce any collisions. The pairs are visited in the order of
+        // the body indices, so the collisions (and the solver) do not depend on anything
+        // but the order of the bodies.
         self.collisions = vec![];
-        let slice = &bodies[..];
-        let length = slice.len();
-        for i in 1..length {
-            let (first, second) = slice.split_at(i);
-            let body1 = & first[i-1];
-            for (j, body2) in second.iter().enumerate() {
-                if body1.is_static && body2.is_static {
-                    continue
+        let mut sensor_overlaps = BTreeSet::new();
+        let pairs = find_pairs(bodies);
+        self.num_pairs = pairs.len();
+        let collision_infos = self.narrowphase(bodies, &pairs);
+        for (&(i, j), collision_infos) in pairs.iter().zip(collision_infos) {
+            if bodies[i].is_sensor || bodies[j].is_sensor {
+                if !collision_infos.is_empty() {
+                    sensor_overlaps.insert((i, j));
                 }
-                let collision_infos = collision_detection::find_collisions(body1, body2);
-                let collisions = &mut collision_infos.iter().map(|&c| Collision{ info: c, body1: i-1, body2: j+i }).collect();
-                self.collisions.append(collisions);
+                continue
+            }
+            let friction = (bodies[i].friction * bodies[j].friction).sqrt();
+            let restitution = bodies[i].restitution.max(bodies[j].restitution);
+            let collisions = &mut collision_infos.iter().map(|&c| Collision { friction, restitution, ..Collision::new(c, i, j) }).collect();
+            self.collisions.append(collisions);
+        }
+        sensor_overlaps
+    }
+
+    fn update_sensor_overlaps(&mut self, sensor_overlaps: BTreeSet<(usize, usize)>, events: &mut Vec<Event<S>>) {
+        for &(body1, body2) in sensor_overlaps.difference(&self.sensor_overlaps) {
+            events.push(Event::SensorEnter { body1, body2 });
+        }
+        for &(body1, body2) in self.sensor_overlaps.difference(&sensor_overlaps) {
+            events.push(Event::SensorExit { body1, body2 });
+        }
+        self.sensor_overlaps = sensor_overlaps;
+    }
+
+}
+
+// The pairs of bodies whose bounding boxes overlap and that may collide, ordered by
+// their indices.
+fn find_pairs<S: Scalar>(bodies: &[Body<S>]) -> Vec<(usize, usize)> {
+    let mut pairs = vec![];
+    let length = bodies.len();
+    for i in 1..length {
+        let (first, second) = bodies.split_at(i);
+        let body1 = & first[i-1];
+        for (j, body2) in second.iter().enumerate() {
+            if body1.is_static && body2.is_static {
+                continue
+            }
+            if !body1.filter.should_collide(&body2.filter) {
+                continue
             }
+            if !body1.shape.get_aabb().overlaps(&body2.shape.get_aabb()) {
+                continue
+            }
+            pairs.push((i-1, j+i));
         }
     }
+    pairs
+}
+
+fn one_way_allows_collision<S: Scalar>(bodies: &[Body<S>], collision: &Collision<S>) -> bool {
+    let body1 = &bodies[collision.body1];
+    let body2 = &bodies[collision.body2];
+    // The collision normal points from body1 to body2.
+    let (platform, other, normal) = match (body1.one_way_direction, body2.one_way_direction) {
+        (Some(_), _) => (body1, body2, collision.info.normal),
+        (None, Some(_)) => (body2, body1, -collision.info.normal),
+        (None, None) => { return true }
+    };
+    let direction = platform.one_way_direction.unwrap().rotate(platform.apos);
+    let pos = collision.info.pos;
+    let relative_velocity = other.vel_at(pos - other.pos) - platform.vel_at(pos - platform.pos);
+    normal * direction > S::zero() && relative_velocity * direction < S::from_f64(ONE_WAY_VELOCITY_TOLERANCE)
+}
+
+pub fn resolve_constraints<S: Scalar>(constraints: &mut [Constraint<S>], joints: &mut [JointConstraint<S>], bodies: &mut [Body<S>]) {
+    for _ in 0..NUM_ITERATIONS {
+        for constraint in constraints.iter_mut() {
+            constraint.resolve(bodies);
+        }
+        for joint in joints.iter_mut() {
+            joint.resolve(bodies);
+        }
+    }
+}
+
+// Same as Body::apply_impulse_at, on the velocities of a dynamic body.
+pub fn apply_impulse_at<S: Scalar, V: Velocities<S> + ?Sized>(velocities: &mut V, body: usize, impulse: Point<S>, pos: Point<S>, mass: S, inertia: S) {
+    velocities.add_vel(body, impulse / mass, pos.orth() * impulse / inertia);
+}
+
+impl<S: Scalar> Velocities<S> for [Body<S>] {
+    fn vel_at(&self, body: usize, relative_pos: Point<S>) -> Point<S> {
+        self[body].vel_at(relative_pos)
+    }
 
+    fn add_vel(&mut self, body: usize, vel: Point<S>, avel: S) {
+        self[body].vel += vel;
+        self[body].avel += avel;
+    }
 }
 
-fn resolve_collision(body1: &mut Body, body2: &mut Body, collision: CollisionInfo) {
-    let r1 = collision.pos - body1.pos;
-    let r2 = collision.pos - body2.pos;
-    let inv_m1 = body1.inv_mass();
-    let inv_m2 = body2.inv_mass();
-    let inv_i1 = body1.inv_inertia();
-    let inv_i2 = body2.inv_inertia();
-    // Normal impulse
-    let relative_velocity_normal = collision.normal * (body1.vel_at(r1) - body2.vel_at(r2)) + (collision.depth - ALLOWED_PENETRATION) * BAUMGARTE_FACTOR;
-    let k_normal = inv_m1 + inv_m2 + (r1 * r1 - (r1 * collision.normal).powi(2)) * inv_i1 + (r2 * r2 - (r2 * collision.normal).powi(2)) * inv_i2;
-    let p_normal = relative_velocity_normal / k_normal;
-    let p = collision.normal * p_normal;
-    if relative_velocity_normal > 0.0 {
-        body1.apply_impulse_at(-p, r1);
-        body2.apply_impulse_at(p, r2);
+impl<S: Scalar> Constraint<S> {
+    pub fn new(index: usize, collision: &Collision<S>, body1: &Body<S>, body2: &Body<S>) -> Constraint<S> {
+        let info = collision.info;
+        let r1 = info.pos - body1.pos;
+        let r2 = info.pos - body2.pos;
+        let inv_m1 = body1.inv_mass();
+        let inv_m2 = body2.inv_mass();
+        let inv_i1 = body1.inv_inertia();
+        let inv_i2 = body2.inv_inertia();
+        let tangent = info.normal.orth();
+        // A bouncing contact separates with the share restitution of the velocity
+        // with which it approaches, unless that is too slow to bounce.
+        let approach_velocity = info.normal * (body1.vel_at(r1) - body2.vel_at(r2));
+        let mut bias = get_bias(info.depth);
+        if collision.restitution > S::zero() && approach_velocity > S::from_f64(RESTITUTION_THRESHOLD) {
+            bias = bias.max(collision.restitution * approach_velocity);
+        }
+        Constraint {
+            collision: index,
+            body1: collision.body1,
+            body2: collision.body2,
+            r1,
+            r2,
+            normal: info.normal,
+            tangent,
+            bias,
+            k_normal: inv_m1 + inv_m2 + (r1 * r1 - (r1 * info.normal).powi(2)) * inv_i1 + (r2 * r2 - (r2 * info.normal).powi(2)) * inv_i2,
+            k_tangent: inv_m1 + inv_m2 + (r1 * r1 - (r1 * tangent).powi(2)) * inv_i1 + (r2 * r2 - (r2 * tangent).powi(2)) * inv_i2,
+            friction: collision.friction,
+            surface_velocity1: info.surface_velocity1,
+            surface_velocity2: info.surface_velocity2,
+            mass1: body1.mass,
+            mass2: body2.mass,
+            inertia1: body1.inertia,
+            inertia2: body2.inertia,
+            is_static1: body1.is_static,
+            is_static2: body2.is_static,
+            normal_impulse: collision.normal_impulse,
+            tangent_impulse: collision.tangent_impulse
+        }
     }
-    // Tangent (friction) impulse
-    let tangent = collision.normal.orth();
-    let relative_velocity_tangent = tangent * (body1.vel_at(r1) - body2.vel_at(r2));
-    let k_tangent = inv_m1 + inv_m2 + (r1 * r1 - (r1 * tangent).powi(2)) * inv_i1 + (r2 * r2 - (r2 * tangent).powi(2)) * inv_i2;
-    let p_tangent = relative_velocity_tangent / k_tangent;
-    let max_p_tangent = FRICTION * p_normal;
-    let p_tangent = clamp(-max_p_tangent, p_tangent, max_p_tangent);
-    let p = tangent * p_tangent;
-    if relative_velocity_tangent > 0.0 {
-        body1.apply_impulse_at(-p, r1);
-        body2.apply_impulse_at(p, r2);
+
+    pub fn resolve<V: Velocities<S> + ?Sized>(&mut self, velocities: &mut V) {
+        let (body1, body2, r1, r2) = (self.body1, self.body2, self.r1, self.r2);
+        // Normal impulse
+        let relative_velocity_normal = self.normal * (velocities.vel_at(body1, r1) - velocities.vel_at(body2, r2)) + self.bias;
+        let p_normal = relative_velocity_normal / self.k_normal;
+        let p = self.normal * p_normal;
+        if relative_velocity_normal > S::zero() {
+            self.apply_impulse(velocities, -p, p);
+            self.normal_impulse += p_normal;
+        }
+        // Tangent (friction) impulse. The surface velocities of both bodies move
+        // along the tangent that is obtained from their respective outward normal.
+        let relative_velocity_tangent = self.tangent * (velocities.vel_at(body1, r1) - velocities.vel_at(body2, r2)) + self.surface_velocity1 + self.surface_velocity2;
+        let p_tangent = relative_velocity_tangent / self.k_tangent;
+        let max_p_tangent = self.friction * p_normal.max(S::zero());
+        let p_tangent = clamp(-max_p_tangent, p_tangent, max_p_tangent);
+        let p = self.tangent * p_tangent;
+        self.apply_impulse(velocities, -p, p);
+        self.tangent_impulse += p_tangent;
     }
+
+    fn apply_impulse<V: Velocities<S> + ?Sized>(&self, velocities: &mut V, impulse1: Point<S>, impulse2: Point<S>) {
+        if !self.is_static1 {
+            apply_impulse_at(velocities, self.body1, impulse1, self.r1, self.mass1, self.inertia1);
+        }
+        if !self.is_static2 {
+            apply_impulse_at(velocities, self.body2, impulse2, self.r2, self.mass2, self.inertia2);
+        }
+    }
+}
+
+// The separating velocity that removes the share BAUMGARTE_FACTOR of the
+// penetration beyond ALLOWED_PENETRATION within one step. Without the division by
+// DT the correction is a distance used as a velocity, which is 100 times too weak:
+// resting boxes then settle about a quarter unit deep and segments sink into them.
+fn get_bias<S: Scalar>(depth: S) -> S {
+    (depth - S::from_f64(ALLOWED_PENETRATION)) * S::from_f64(BAUMGARTE_FACTOR) / S::from_f64(DT)
 }
 
-pub fn clamp(min: f64, x: f64, max: f64) -> f64 {
+pub fn clamp<S: Scalar>(min: S, x: S, max: S) -> S {
     min.max(x.min(max))
 }
 
@@ -0,0 +1,122 @@
+use point::Point;
+use scalar::Scalar;
+use simulation::aabb::Aabb;
+use simulation::polygon::Polygon;
+use simulation::shape::{Shape, Project};
+
+// A rigid union of convex polygons, for example the pieces of a concave polygon.
+#[derive(Debug, Clone)]
+pub struct Compound<S = f64> {
+    pub pos: Point<S>,
+    pub children: Vec<Shape<S>>,
+    areas: Vec<S>,
+    offsets: Vec<Point<S>>
+}
+
+impl<S: Scalar> Compound<S> {
+    pub fn new(children: Vec<Polygon<S>>) -> Compound<S> {
+        let areas: Vec<S> = children.iter().map(|child| child.get_area()).collect();
+        let total_area = areas.iter().fold(S::zero(), |acc, &area| acc + area);
+        let pos = children.iter().zip(areas.iter()).fold(Point::zero(), |acc, (child, &area)| acc + child.get_centroid() * area) / total_area;
+        Compound::with_pos(children, pos)
+    }
+
+    // Uses the given position as the reference point instead of the center of the children.
+    pub fn with_pos(children: Vec<Polygon<S>>, pos: Point<S>) -> Compound<S> {
+        let areas: Vec<S> = children.iter().map(|child| child.get_area()).collect();
+        let offsets = children.iter().map(|child| child.pos - pos).collect();
+        Compound {
+            pos,
+            children: children.into_iter().map(Shape::Polygon).collect(),
+            areas,
+            offsets
+        }
+    }
+
+    // A compound at the origin with the children placed at the given offsets.
+    pub fn from_offsets(children: Vec<Polygon<S>>, offsets: Vec<Point<S>>) -> Compound<S> {
+        let areas: Vec<S> = children.iter().map(|child| child.get_area()).collect();
+        let mut compound = Compound {
+            pos: Point::zero(),
+            children: children.into_iter().map(Shape::Polygon).collect(),
+            areas,
+            offsets
+        };
+        compound.update_pos(Point::zero(), S::zero());
+        compound
+    }
+
+    pub fn get_offsets(&self) -> &[Point<S>] {
+        &self.offsets
+    }
+
+    pub fn project(&self, normal: Point<S>) -> [S;2] {
+        self.children.iter().map(|child| child.project(normal)).fold([S::max_value(), S::min_value()], |acc, x| [acc[0].min(x[0]), acc[1].max(x[1])])
+    }
+
+    pub fn support(&self, direction: Point<S>) -> Point<S> {
+        let supports: Vec<Point<S>> = self.children.iter().map(|child| child.support(direction)).collect();
+        supports[1..].iter().fold(supports[0], |best, &x| if x * direction > best * direction { x } else { best })
+    }
+
+    pub fn update_pos(&mut self, pos: Point<S>, apos: S) {
+        self.pos = pos;
+        for (child, offset) in self.children.iter_mut().zip(self.offsets.iter()) {
+            child.update_pos(pos + offset.rotate(apos), apos);
+        }
+    }
+
+    pub fn get_aabb(&self) -> Aabb<S> {
+        let corners: Vec<Point<S>> = self.children.iter().flat_map(|child| {
+            let aabb = child.get_aabb();
+            vec![aabb.min, aabb.max]
+        }).collect();
+        Aabb::from_points(&corners)
+    }
+
+    pub fn get_moment_of_inertia(&self) -> S {
+        // The children are moved to the center of the compound with the parallel axis
+        // theorem, which needs their inertia about their centroid.
+        let mut inertia = S::zero();
+        for ((child, &area), &offset) in self.children.iter().zip(self.areas.iter()).zip(self.offsets.iter()) {
+            let (child_inertia, offset) = match *child {
+                Shape::Polygon(ref polygon) => {
+                    let centroid = polygon.get_centroid();
+                    let shift = centroid - polygon.pos;
+                    (polygon.get_moment_of_inertia() - shift * shift, centroid - self.pos)
+                }
+                ref shape => { (shape.get_moment_of_inertia(), offset) }
+            };
+            inertia += area * (child_inertia + offset * offset);
+        }
+        inertia / self.areas.iter().fold(S::zero(), |acc, &area| acc + area)
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn test_moment_of_inertia_of_split_square() {
+        // Two halves of the unit square have the inertia of the square.
+        let left = Polygon::new(vec![Point::new(0.0, 0.0), Point::new(0.5, 0.0), Point::new(0.5, 1.0), Point::new(0.0, 1.0)]);
+        let right = Polygon::new(vec![Point::new(0.5, 0.0), Point::new(1.0, 0.0), Point::new(1.0, 1.0), Point::new(0.5, 1.0)]);
+        let compound = Compound::new(vec![left, right]);
+        assert!((compound.pos - Point::new(0.5, 0.5)).norm() < 0.00001);
+        assert!((compound.get_moment_of_inertia() - 1.0 / 6.0).abs() < 0.00001);
+    }
+
+    #[test]
+    fn test_l_shape_of_trapezoids() {
+        // An L of three unit squares, split into two trapezoids whose vertex
+        // averages are not their centroids.
+        let bottom = Polygon::new(vec![Point::new(0.0, 0.0), Point::new(2.0, 0.0), Point::new(2.0, 1.0), Point::new(1.0, 1.0)]);
+        let left = Polygon::new(vec![Point::new(0.0, 0.0), Point::new(1.0, 1.0), Point::new(1.0, 2.0), Point::new(0.0, 2.0)]);
+        let mut compound = Compound::new(vec![bottom, left]);
+        assert!((compound.pos - Point::new(5.0 / 6.0, 5.0 / 6.0)).norm() < 0.00001);
+        assert!((compound.get_moment_of_inertia() - 11.0 / 18.0).abs() < 0.00001);
+        compound.update_pos(Point::new(3.0, -1.0), 1.0);
+        assert!((compound.get_moment_of_inertia() - 11.0 / 18.0).abs() < 0.00001);
+    }
+}
@@ -0,0 +1,132 @@
+use point::Point;
+use scalar::Scalar;
+use simulation::polygon::{signed_area, validate};
+
+// Splits simple polygons into convex pieces. The vertices have to be in
+// counterclockwise order.
+
+fn turn<S: Scalar>(previous: Point<S>, vertex: Point<S>, next: Point<S>) -> S {
+    // Positive for left (convex) turns of counterclockwise polygons.
+    (vertex - previous).orth() * (next - vertex)
+}
+
+fn is_in_triangle<S: Scalar>(point: Point<S>, a: Point<S>, b: Point<S>, c: Point<S>) -> bool {
+    turn(a, b, point) >= S::zero() && turn(b, c, point) >= S::zero() && turn(c, a, point) >= S::zero()
+}
+
+pub fn triangulate<S: Scalar>(vertices: &[Point<S>]) -> Vec<[usize; 3]> {
+    // Ear clipping. Returns triangles of indices into vertices.
+    let mut remaining: Vec<usize> = (0..vertices.len()).collect();
+    let mut triangles = vec![];
+    while remaining.len() > 3 {
+        let num_remaining = remaining.len();
+        let ear = (0..num_remaining).find(|&i| {
+            let previous = remaining[(i + num_remaining - 1) % num_remaining];
+            let next = remaining[(i + 1) % num_remaining];
+            let (a, b, c) = (vertices[previous], vertices[remaining[i]], vertices[next]);
+            // Only reflex vertices can lie inside an ear.
+            turn(a, b, c) > S::zero() && (0..num_remaining).all(|j| {
+                let point = vertices[remaining[j]];
+                let is_reflex = turn(vertices[remaining[(j + num_remaining - 1) % num_remaining]], point, vertices[remaining[(j + 1) % num_remaining]]) <= S::zero();
+                !is_reflex || point == a || point == b || point == c || !is_in_triangle(point, a, b, c)
+            })
+        });
+        match ear {
+            Some(i) => {
+                let previous = remaining[(i + num_remaining - 1) % num_remaining];
+                let next = remaining[(i + 1) % num_remaining];
+                triangles.push([previous, remaining[i], next]);
+                remaining.remove(i);
+            }
+            None => {
+                // Only collinear vertices are left which do not form any triangle.
+                let collinear = (0..num_remaining).find(|&i| {
+                    let previous = remaining[(i + num_remaining - 1) % num_remaining];
+                    let next = remaining[(i + 1) % num_remaining];
+                    turn(vertices[previous], vertices[remaining[i]], vertices[next]) == S::zero()
+                });
+                match collinear {
+                    Some(i) => { remaining.remove(i); }
+                    None => { break }
+                }
+            }
+        }
+    }
+    if remaining.len() == 3 && signed_area(&[vertices[remaining[0]], vertices[remaining[1]], vertices[remaining[2]]]) > S::zero() {
+        triangles.push([remaining[0], remaining[1], remaining[2]]);
+    }
+    triangles
+}
+
+fn merge(piece1: &[usize], piece2: &[usize]) -> Option<Vec<usize>> {
+    // Merge two pieces along their shared diagonal, if they have one.
+    for i in 0..piece1.len() {
+        let a = piece1[i];
+        let b = piece1[(i + 1) % piece1.len()];
+        for j in 0..piece2.len() {
+            if piece2[j] == b && piece2[(j + 1) % piece2.len()] == a {
+                let mut merged: Vec<usize> = (0..piece1.len()).map(|k| piece1[(i + 1 + k) % piece1.len()]).collect();
+                merged.extend((2..piece2.len()).map(|k| piece2[(j + k) % piece2.len()]));
+                return Some(merged)
+            }
+        }
+    }
+    None
+}
+
+pub fn decompose<S: Scalar>(vertices: &[Point<S>]) -> Vec<Vec<Point<S>>> {
+    // Hertel–Mehlhorn: start from a triangulation and remove every diagonal whose
+    // removal keeps both neighbouring pieces convex. The result has at most four
+    // times the minimal number of convex pieces.
+    let mut pieces: Vec<Vec<usize>> = triangulate(vertices).iter().map(|triangle| triangle.to_vec()).collect();
+    let mut i = 0;
+    while i < pieces.len() {
+        let mut j = i + 1;
+        while j < pieces.len() {
+            let merged = merge(&pieces[i], &pieces[j]).filter(|merged| {
+                let points: Vec<Point<S>> = merged.iter().map(|&k| vertices[k]).collect();
+                validate(&points).is_convex
+            });
+            match merged {
+                Some(merged) => {
+                    // The merged piece may now share diagonals with pieces that were checked before.
+                    pieces[i] = merged;
+                    pieces.remove(j);
+                    j = i + 1;
+                }
+                None => { j += 1 }
+            }
+        }
+        i += 1;
+    }
+    pieces.iter().map(|piece| piece.iter().map(|&k| vertices[k]).collect()).collect()
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn l_shape() -> Vec<Point> {
+        vec![Point::new(0.0, 0.0), Point::new(2.0, 0.0), Point::new(2.0, 1.0), Point::new(1.0, 1.0), Point::new(1.0, 2.0), Point::new(0.0, 2.0)]
+    }
+
+    #[test]
+    fn test_triangulate() {
+        let vertices = l_shape();
+        let triangles = triangulate(&vertices);
+        assert_eq!(triangles.len(), 4);
+        let area: f64 = triangles.iter().map(|t| signed_area(&[vertices[t[0]], vertices[t[1]], vertices[t[2]]])).sum();
+        assert!((area - 3.0).abs() < 0.00001);
+    }
+
+    #[test]
+    fn test_decompose() {
+        let pieces = decompose(&l_shape());
+        assert_eq!(pieces.len(), 2);
+        let area: f64 = pieces.iter().map(|piece| signed_area(piece)).sum();
+        assert!((area - 3.0).abs() < 0.00001);
+        for piece in pieces.iter() {
+            assert!(validate(piece).is_valid_convex());
+        }
+    }
+}
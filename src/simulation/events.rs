@@ -0,0 +1,12 @@
+use simulation::collisions::Contact;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event<S = f64> {
+    SensorEnter { body1: usize, body2: usize },
+    SensorExit { body1: usize, body2: usize },
+    ContactBegin(Contact<S>),
+    ContactPersist(Contact<S>),
+    ContactEnd { body1: usize, body2: usize },
+    // The parent index is reused by the first child.
+    BodyBroken { parent: usize, children: Vec<usize> },
+}
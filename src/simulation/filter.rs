@@ -0,0 +1,63 @@
+use serde::{Serialize, Deserialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CollisionFilter {
+    pub category_bits: u16,
+    pub mask_bits: u16,
+    pub group_index: i16
+}
+
+impl Default for CollisionFilter {
+    fn default() -> CollisionFilter {
+        CollisionFilter::new()
+    }
+}
+
+impl CollisionFilter {
+    pub fn new() -> CollisionFilter {
+        CollisionFilter {
+            category_bits: 0x0001,
+            mask_bits: 0xFFFF,
+            group_index: 0
+        }
+    }
+
+    pub fn should_collide(&self, other: &CollisionFilter) -> bool {
+        // Bodies in the same (non-zero) group always collide if the group is
+        // positive and never collide if it is negative, regardless of their bits.
+        if self.group_index == other.group_index && self.group_index != 0 {
+            return self.group_index > 0
+        }
+        (self.mask_bits & other.category_bits) != 0 && (self.category_bits & other.mask_bits) != 0
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    #[test]
+    fn test_default_filters_collide() {
+        assert!(CollisionFilter::new().should_collide(&CollisionFilter::new()));
+    }
+
+    #[test]
+    fn test_category_and_mask() {
+        let player = CollisionFilter { category_bits: 0x0002, mask_bits: 0xFFFF ^ 0x0004, group_index: 0 };
+        let bullet = CollisionFilter { category_bits: 0x0004, mask_bits: 0xFFFF, group_index: 0 };
+        let wall = CollisionFilter::new();
+        assert!(!player.should_collide(&bullet));
+        assert!(!bullet.should_collide(&player));
+        assert!(bullet.should_collide(&wall));
+        assert!(player.should_collide(&wall));
+    }
+
+    #[test]
+    fn test_group_index() {
+        let limb = CollisionFilter { category_bits: 0x0001, mask_bits: 0xFFFF, group_index: -1 };
+        assert!(!limb.should_collide(&limb));
+        let mut other = CollisionFilter { category_bits: 0x0002, mask_bits: 0x0000, group_index: 1 };
+        assert!(other.should_collide(&other));
+        other.group_index = 2;
+        assert!(!limb.should_collide(&other));
+    }
+}
@@ -0,0 +1,57 @@
+use serde::{Serialize, Deserialize};
+
+use point::Point;
+use scalar::Scalar;
+use simulation::body::Body;
+
+// An acceleration that acts on the dynamic bodies in addition to gravity. Unlike
+// gravity it is not scaled by the gravity scale of the bodies.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ForceField<S = f64> {
+    // The same acceleration everywhere, for example wind.
+    Uniform { acceleration: Point<S> },
+    // Pulls the bodies whose center is within radius of the center toward it, or
+    // pushes them away if the strength is negative.
+    Radial { center: Point<S>, strength: S, radius: S }
+}
+
+impl<S: Scalar> ForceField<S> {
+    pub fn apply(&self, body: &mut Body<S>) {
+        match *self {
+            ForceField::Uniform { acceleration } => {
+                body.apply_force(acceleration * body.mass);
+            }
+            ForceField::Radial { center, strength, radius } => {
+                let offset = center - body.pos;
+                let distance = offset.norm();
+                if distance > S::zero() && distance <= radius {
+                    body.apply_force(offset * (strength * body.mass / distance));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use simulation::body;
+
+    #[test]
+    fn test_radial() {
+        let field = ForceField::Radial { center: Point::new(0.0, 0.0), strength: 2.0, radius: 5.0 };
+        let mut near = body::get_circle(Point::new(3.0, 4.0), 2.0, 0.5);
+        let mut far = body::get_circle(Point::new(6.0, 0.0), 2.0, 0.5);
+        let mut center = body::get_circle(Point::new(0.0, 0.0), 2.0, 0.5);
+        for body in [&mut near, &mut far, &mut center].iter_mut() {
+            field.apply(body);
+        }
+        assert!((near.acc - Point::new(-1.2, -1.6)).norm() < 0.00001);
+        assert_eq!(far.acc, Point::zero());
+        assert_eq!(center.acc, Point::zero());
+        let mut wall = body::get_rectangle(Point::new(1.0, 0.0), 1.0, 1.0, 0.0);
+        ForceField::Uniform { acceleration: Point::new(1.0, 0.0) }.apply(&mut wall);
+        assert_eq!(wall.acc, Point::zero());
+    }
+}
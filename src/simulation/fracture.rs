@@ -0,0 +1,96 @@
+use std::collections::BTreeMap;
+use std::f64;
+
+use point::Point;
+use scalar::Scalar;
+use simulation::collisions::Collision;
+use simulation::polygon::signed_area;
+use super::{NUM_SHARDS, DISTANCE_TOLERANCE};
+
+// Sums the normal impulses of the enabled collisions of every body. Also returns
+// the point at which the impulses act on average.
+pub fn get_impulses<S: Scalar>(collisions: &[Collision<S>]) -> BTreeMap<usize, (S, Point<S>)> {
+    let mut impulses: BTreeMap<usize, (S, Point<S>)> = BTreeMap::new();
+    for collision in collisions.iter().filter(|c| c.enabled && c.normal_impulse > S::zero()) {
+        for &body in [collision.body1, collision.body2].iter() {
+            let entry = impulses.entry(body).or_insert((S::zero(), Point::zero()));
+            entry.0 += collision.normal_impulse;
+            entry.1 += collision.info.pos * collision.normal_impulse;
+        }
+    }
+    for value in impulses.values_mut() {
+        value.1 = value.1 / value.0;
+    }
+    impulses
+}
+
+// Seeds on a sunflower spiral around the impact point, denser close to it.
+// They are deterministic, so that fractures can be reproduced.
+pub fn get_seeds<S: Scalar>(impact: Point<S>, vertices: &[Point<S>]) -> Vec<Point<S>> {
+    let size = vertices.iter().fold(S::zero(), |acc, &vertex| acc.max((vertex - impact).norm()));
+    let golden_angle = f64::consts::PI * (3.0 - 5.0f64.sqrt());
+    (0..NUM_SHARDS).map(|k| {
+        let radius = size * S::from_f64(((k as f64 + 0.5) / NUM_SHARDS as f64).powi(2));
+        let angle = S::from_f64(golden_angle * k as f64);
+        impact + Point::new(angle.cos(), angle.sin()) * radius
+    }).collect()
+}
+
+pub fn clip_half_plane<S: Scalar>(polygon: &[Point<S>], point: Point<S>, normal: Point<S>) -> Vec<Point<S>> {
+    // Sutherland–Hodgman: keep the part of the polygon with (x - point) * normal <= 0.
+    let mut clipped = vec![];
+    for i in 0..polygon.len() {
+        let current = polygon[i];
+        let next = polygon[(i + 1) % polygon.len()];
+        let distance_current = (current - point) * normal;
+        let distance_next = (next - point) * normal;
+        if distance_current <= S::zero() {
+            clipped.push(current);
+        }
+        if (distance_current < S::zero() && distance_next > S::zero()) || (distance_current > S::zero() && distance_next < S::zero()) {
+            clipped.push(current + (next - current) * (distance_current / (distance_current - distance_next)));
+        }
+    }
+    clipped
+}
+
+// The Voronoi cells of the seeds within a convex polygon. Cells without area are
+// skipped. Neighbouring cells are moved apart by twice gap, so that polygons with
+// a radius of gap built from them touch without overlapping.
+pub fn voronoi_shards<S: Scalar>(vertices: &[Point<S>], seeds: &[Point<S>], gap: S) -> Vec<Vec<Point<S>>> {
+    let tolerance = S::from_f64(DISTANCE_TOLERANCE);
+    let mut shards = vec![];
+    for (i, &seed) in seeds.iter().enumerate() {
+        let mut shard = vertices.to_vec();
+        for (j, &other) in seeds.iter().enumerate() {
+            if i == j || shard.is_empty() || (other - seed).norm() < tolerance {
+                continue
+            }
+            let normal = (other - seed).normalized();
+            shard = clip_half_plane(&shard, seed.middle(other) - normal * gap, normal);
+        }
+        if shard.len() >= 3 && signed_area(&shard).abs() > tolerance {
+            shards.push(shard);
+        }
+    }
+    shards
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn test_voronoi_shards_cover_polygon() {
+        let square = vec![Point::new(0.0, 0.0), Point::new(2.0, 0.0), Point::new(2.0, 2.0), Point::new(0.0, 2.0)];
+        let shards = voronoi_shards(&square, &get_seeds(Point::new(0.5, 0.0), &square), 0.0);
+        assert!(shards.len() > 1);
+        let area: f64 = shards.iter().map(|shard| signed_area(shard)).sum();
+        assert!((area - 4.0).abs() < 0.00001);
+        let halves = voronoi_shards(&square, &[Point::new(0.5, 1.0), Point::new(1.5, 1.0)], 0.0);
+        assert_eq!(halves.len(), 2);
+        assert!((signed_area(&halves[0]) - 2.0).abs() < 0.00001);
+        let apart = voronoi_shards(&square, &[Point::new(0.5, 1.0), Point::new(1.5, 1.0)], 0.1);
+        assert!((signed_area(&apart[0]) - 1.8).abs() < 0.00001);
+    }
+}
@@ -0,0 +1,195 @@
+use point::Point;
+use scalar::Scalar;
+use simulation::shape::Shape;
+
+const MAX_ITERATIONS: usize = 50;
+const TOLERANCE: f64 = 0.0000000001;
+
+#[derive(Debug, Clone, Copy)]
+pub struct DistanceResult<S = f64> {
+    // Distance between the shapes, zero if they overlap.
+    pub distance: S,
+    // Closest points on both shapes, including their radius.
+    pub point1: Point<S>,
+    pub point2: Point<S>
+}
+
+#[derive(Debug, Clone, Copy)]
+struct SimplexVertex<S> {
+    point1: Point<S>,
+    point2: Point<S>,
+    // point1 - point2, a point of the Minkowski difference of the two cores
+    point: Point<S>,
+    weight: S
+}
+
+fn support<S: Scalar>(shape1: &Shape<S>, shape2: &Shape<S>, direction: Point<S>) -> SimplexVertex<S> {
+    let point1 = shape1.support_core(direction);
+    let point2 = shape2.support_core(-direction);
+    SimplexVertex { point1, point2, point: point1 - point2, weight: S::one() }
+}
+
+fn cross<S: Scalar>(a: Point<S>, b: Point<S>) -> S {
+    a.orth() * b
+}
+
+fn with_weights<S: Scalar>(vertices: &[SimplexVertex<S>], weights: &[S]) -> Vec<SimplexVertex<S>> {
+    vertices.iter().zip(weights.iter()).map(|(&vertex, &weight)| SimplexVertex { weight, ..vertex }).collect()
+}
+
+fn solve_segment<S: Scalar>(vertices: &[SimplexVertex<S>]) -> Vec<SimplexVertex<S>> {
+    // Reduce the simplex to the feature of the segment that is closest to the origin.
+    let (w1, w2) = (vertices[0].point, vertices[1].point);
+    let edge = w2 - w1;
+    let d_2 = -(w1 * edge);
+    let d_1 = w2 * edge;
+    if d_2 <= S::zero() {
+        return with_weights(&vertices[0..1], &[S::one()])
+    }
+    if d_1 <= S::zero() {
+        return with_weights(&vertices[1..2], &[S::one()])
+    }
+    with_weights(vertices, &[d_1 / (d_1 + d_2), d_2 / (d_1 + d_2)])
+}
+
+fn solve_triangle<S: Scalar>(vertices: &[SimplexVertex<S>]) -> Vec<SimplexVertex<S>> {
+    // Reduce the simplex to the feature of the triangle that is closest to the origin,
+    // using the barycentric coordinates of the origin with respect to the edges and the triangle.
+    // See Box2D, b2Simplex::Solve3
+    let (w1, w2, w3) = (vertices[0].point, vertices[1].point, vertices[2].point);
+    let e12 = w2 - w1;
+    let d12_1 = w2 * e12;
+    let d12_2 = -(w1 * e12);
+    let e13 = w3 - w1;
+    let d13_1 = w3 * e13;
+    let d13_2 = -(w1 * e13);
+    let e23 = w3 - w2;
+    let d23_1 = w3 * e23;
+    let d23_2 = -(w2 * e23);
+    let n123 = cross(e12, e13);
+    let d123_1 = n123 * cross(w2, w3);
+    let d123_2 = n123 * cross(w3, w1);
+    let d123_3 = n123 * cross(w1, w2);
+    if d12_2 <= S::zero() && d13_2 <= S::zero() {
+        return with_weights(&[vertices[0]], &[S::one()])
+    }
+    if d12_1 > S::zero() && d12_2 > S::zero() && d123_3 <= S::zero() {
+        return with_weights(&[vertices[0], vertices[1]], &[d12_1 / (d12_1 + d12_2), d12_2 / (d12_1 + d12_2)])
+    }
+    if d13_1 > S::zero() && d13_2 > S::zero() && d123_2 <= S::zero() {
+        return with_weights(&[vertices[0], vertices[2]], &[d13_1 / (d13_1 + d13_2), d13_2 / (d13_1 + d13_2)])
+    }
+    if d12_1 <= S::zero() && d23_2 <= S::zero() {
+        return with_weights(&[vertices[1]], &[S::one()])
+    }
+    if d13_1 <= S::zero() && d23_1 <= S::zero() {
+        return with_weights(&[vertices[2]], &[S::one()])
+    }
+    if d23_1 > S::zero() && d23_2 > S::zero() && d123_1 <= S::zero() {
+        return with_weights(&[vertices[1], vertices[2]], &[d23_1 / (d23_1 + d23_2), d23_2 / (d23_1 + d23_2)])
+    }
+    let sum = d123_1 + d123_2 + d123_3;
+    with_weights(vertices, &[d123_1 / sum, d123_2 / sum, d123_3 / sum])
+}
+
+fn closest_point<S: Scalar>(simplex: &[SimplexVertex<S>]) -> Point<S> {
+    simplex.iter().fold(Point::zero(), |acc, vertex| acc + vertex.point * vertex.weight)
+}
+
+fn witness_points<S: Scalar>(simplex: &[SimplexVertex<S>]) -> (Point<S>, Point<S>) {
+    let point1 = simplex.iter().fold(Point::zero(), |acc, vertex| acc + vertex.point1 * vertex.weight);
+    let point2 = simplex.iter().fold(Point::zero(), |acc, vertex| acc + vertex.point2 * vertex.weight);
+    (point1, point2)
+}
+
+fn run_gjk<S: Scalar>(shape1: &Shape<S>, shape2: &Shape<S>) -> Vec<SimplexVertex<S>> {
+    // Returns the simplex of the closest features of the two cores. If it
+    // has three vertices the cores overlap.
+    let tolerance = S::from_f64(TOLERANCE);
+    let x_axis = Point::new(S::one(), S::zero());
+    let initial_direction = shape2.support_core(x_axis) - shape1.support_core(x_axis);
+    let initial_direction = if initial_direction.norm() > S::zero() { initial_direction } else { x_axis };
+    let mut simplex = vec![support(shape1, shape2, -initial_direction)];
+    for _ in 0..MAX_ITERATIONS {
+        simplex = match simplex.len() {
+            1 => { simplex }
+            2 => { solve_segment(&simplex) }
+            _ => { solve_triangle(&simplex) }
+        };
+        if simplex.len() == 3 {
+            break
+        }
+        let closest = closest_point(&simplex);
+        if closest * closest < tolerance {
+            break
+        }
+        let vertex = support(shape1, shape2, -closest);
+        let is_duplicate = simplex.iter().any(|v| v.point == vertex.point);
+        let made_progress = closest * closest - vertex.point * closest > tolerance * (closest * closest);
+        if is_duplicate || !made_progress {
+            break
+        }
+        simplex.push(vertex);
+    }
+    simplex
+}
+
+pub fn distance<S: Scalar>(shape1: &Shape<S>, shape2: &Shape<S>) -> DistanceResult<S> {
+    let simplex = run_gjk(shape1, shape2);
+    let (core1, core2) = witness_points(&simplex);
+    let core_distance = (core2 - core1).norm();
+    let radius1 = shape1.get_radius();
+    let radius2 = shape2.get_radius();
+    if simplex.len() == 3 || core_distance <= radius1 + radius2 {
+        return DistanceResult { distance: S::zero(), point1: core1, point2: core2 }
+    }
+    let normal = (core2 - core1) / core_distance;
+    DistanceResult {
+        distance: core_distance - radius1 - radius2,
+        point1: core1 + normal * radius1,
+        point2: core2 - normal * radius2
+    }
+}
+
+pub fn intersect<S: Scalar>(shape1: &Shape<S>, shape2: &Shape<S>) -> bool {
+    distance(shape1, shape2).distance == S::zero()
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use simulation::polygon::Polygon;
+    use simulation::circle::Circle;
+    use simulation::capsule::Capsule;
+
+    fn square(x: f64, y: f64) -> Shape {
+        Shape::Polygon(Polygon::new(vec![Point::new(x, y), Point::new(x + 1.0, y), Point::new(x + 1.0, y + 1.0), Point::new(x, y + 1.0)]))
+    }
+
+    #[test]
+    fn test_distance_squares() {
+        let result = distance(&square(0.0, 0.0), &square(3.0, 0.5));
+        assert!((result.distance - 2.0).abs() < 0.00001);
+        assert!((result.point1.x - 1.0).abs() < 0.00001);
+        assert!((result.point2.x - 3.0).abs() < 0.00001);
+        assert!(!intersect(&square(0.0, 0.0), &square(3.0, 0.5)));
+        assert!(intersect(&square(0.0, 0.0), &square(0.5, 0.5)));
+    }
+
+    #[test]
+    fn test_distance_circle_capsule() {
+        let circle = Shape::Circle(Circle::new(Point::new(0.0, 3.0), 1.0));
+        let capsule = Shape::Capsule(Capsule::new(Point::new(-2.0, 0.0), Point::new(2.0, 0.0), 0.5));
+        let result = distance(&capsule, &circle);
+        assert!((result.distance - 1.5).abs() < 0.00001);
+        assert!((result.point1 - Point::new(0.0, 0.5)).norm() < 0.00001);
+        assert!((result.point2 - Point::new(0.0, 2.0)).norm() < 0.00001);
+    }
+
+    #[test]
+    fn test_support_zero_direction() {
+        let capsule = Shape::Capsule(Capsule::new(Point::new(-2.0, 0.0), Point::new(2.0, 0.0), 0.5));
+        let point = capsule.support(Point::zero());
+        assert!(point.x.partial_cmp(&point.x).is_some() && point.y.partial_cmp(&point.y).is_some());
+    }
+}
@@ -0,0 +1,555 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::path::Path;
+
+use point::Point;
+use scalar::Scalar;
+use simulation::aabb::Aabb;
+use simulation::polygon::{Polygon, signed_area, segments_intersect};
+use simulation::compound::Compound;
+use simulation::decomposition;
+
+// An occupancy grid of square cells, stored row by row with row 0 at the top.
+// Cells with a density of zero are empty.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cells {
+    pub width: usize,
+    pub height: usize,
+    pub cell_size: f64,
+    pub densities: Vec<f64>
+}
+
+// A body shape made of cells. The collision geometry consists of the convex
+// pieces of the outlines of the solid cells. The cells themselves are input
+// data and stay in f64.
+#[derive(Debug, Clone)]
+pub struct Grid<S = f64> {
+    pub pos: Point<S>,
+    pub apos: S,
+    pub cells: Cells,
+    // Center of mass relative to the top left corner of the cells.
+    pub center: Point<S>,
+    pub compound: Compound<S>,
+    // The surface velocity of all pieces, see Shape::get_surface_velocity.
+    pub surface_velocity: S
+}
+
+impl Cells {
+    pub fn new(width: usize, height: usize, cell_size: f64) -> Cells {
+        Cells { width, height, cell_size, densities: vec![0.0; width * height] }
+    }
+
+    pub fn from_densities(width: usize, height: usize, cell_size: f64, densities: Vec<f64>) -> Result<Cells, String> {
+        match width.checked_mul(height) {
+            Some(num_cells) if num_cells == densities.len() => { Ok(Cells { width, height, cell_size, densities }) }
+            Some(num_cells) => { Err(format!("expected {} densities, got {}", num_cells, densities.len())) }
+            None => { Err("grid is too large".to_string()) }
+        }
+    }
+
+    pub fn has_solid_cells(&self) -> bool {
+        self.densities.iter().any(|&density| density > 0.0)
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> f64 {
+        self.densities[y * self.width + x]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, density: f64) {
+        self.densities[y * self.width + x] = density;
+    }
+
+    // Cells outside of the grid are empty.
+    pub fn is_solid(&self, x: isize, y: isize) -> bool {
+        x >= 0 && y >= 0 && (x as usize) < self.width && (y as usize) < self.height && self.get(x as usize, y as usize) > 0.0
+    }
+
+    pub fn get_cell_center(&self, x: usize, y: usize) -> Point {
+        Point::new((x as f64 + 0.5) * self.cell_size, (y as f64 + 0.5) * self.cell_size)
+    }
+
+    pub fn get_mass(&self) -> f64 {
+        self.densities.iter().sum::<f64>() * self.cell_size.powi(2)
+    }
+
+    pub fn get_center_of_mass(&self) -> Point {
+        let mut weighted = Point::new(0.0, 0.0);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                weighted += self.get_cell_center(x, y) * self.get(x, y);
+            }
+        }
+        weighted / self.densities.iter().sum::<f64>()
+    }
+
+    pub fn get_moment_of_inertia(&self) -> f64 {
+        // Per unit mass around the center of mass. Each cell is a square with
+        // an inertia of size^2 / 6 around its own center.
+        let center = self.get_center_of_mass();
+        let mut inertia = 0.0;
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let offset = self.get_cell_center(x, y) - center;
+                inertia += self.get(x, y) * (self.cell_size.powi(2) / 6.0 + offset * offset);
+            }
+        }
+        inertia / self.densities.iter().sum::<f64>()
+    }
+
+    // Empties the cells whose centers are within radius of center. Returns whether
+    // any cell was changed.
+    pub fn carve_circle(&mut self, center: Point, radius: f64) -> bool {
+        let mut changed = false;
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if self.get(x, y) > 0.0 && (self.get_cell_center(x, y) - center).norm() <= radius {
+                    self.set(x, y, 0.0);
+                    changed = true;
+                }
+            }
+        }
+        changed
+    }
+
+    // Splits the solid cells into parts that are connected through the sides of
+    // their cells, matching the outlines. Each part is cropped to its bounding box
+    // and returned with the position of its top left corner relative to these cells.
+    pub fn get_components(&self) -> Vec<(Point, Cells)> {
+        let mut labels: Vec<Option<usize>> = vec![None; self.width * self.height];
+        let mut components = vec![];
+        for start in 0..self.width * self.height {
+            if labels[start].is_some() || self.densities[start] <= 0.0 {
+                continue
+            }
+            let label = components.len();
+            let mut stack = vec![start];
+            let mut members = vec![];
+            labels[start] = Some(label);
+            while let Some(index) = stack.pop() {
+                members.push(index);
+                let (x, y) = ((index % self.width) as isize, (index / self.width) as isize);
+                for &(nx, ny) in [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)].iter() {
+                    if self.is_solid(nx, ny) {
+                        let neighbour = ny as usize * self.width + nx as usize;
+                        if labels[neighbour].is_none() {
+                            labels[neighbour] = Some(label);
+                            stack.push(neighbour);
+                        }
+                    }
+                }
+            }
+            let min_x = members.iter().map(|&index| index % self.width).min().unwrap();
+            let max_x = members.iter().map(|&index| index % self.width).max().unwrap();
+            let min_y = members.iter().map(|&index| index / self.width).min().unwrap();
+            let max_y = members.iter().map(|&index| index / self.width).max().unwrap();
+            let mut cells = Cells::new(max_x - min_x + 1, max_y - min_y + 1, self.cell_size);
+            for &index in members.iter() {
+                cells.set(index % self.width - min_x, index / self.width - min_y, self.densities[index]);
+            }
+            components.push((Point::new(min_x as f64, min_y as f64) * self.cell_size, cells));
+        }
+        components
+    }
+
+    pub fn get_outlines(&self) -> Vec<Vec<Point>> {
+        // Marching squares over the cell centers. Each outline keeps the solid cells
+        // on its left, so outer outlines are counterclockwise and holes clockwise.
+        // Diagonally touching cells are not connected.
+        // The points are in doubled integer coordinates (cell centers are odd), so that
+        // the midpoints between cell centers are integers and can be linked exactly.
+        let mut edges: BTreeMap<(i64, i64), (i64, i64)> = BTreeMap::new();
+        for window_y in -1..self.height as isize {
+            for window_x in -1..self.width as isize {
+                let corners = [(window_x, window_y), (window_x + 1, window_y), (window_x + 1, window_y + 1), (window_x, window_y + 1)];
+                let solid: Vec<bool> = corners.iter().map(|&(x, y)| self.is_solid(x, y)).collect();
+                let doubled = |k: usize| (2 * corners[k].0 as i64 + 1, 2 * corners[k].1 as i64 + 1);
+                // Midpoint k lies between corner k and corner k + 1.
+                let midpoint = |k: usize| {
+                    let (a, b) = (doubled(k), doubled((k + 1) % 4));
+                    ((a.0 + b.0) / 2, (a.1 + b.1) / 2)
+                };
+                let changes: Vec<usize> = (0..4).filter(|&k| solid[k] != solid[(k + 1) % 4]).collect();
+                let segments = match changes.len() {
+                    2 => { vec![(changes[0], changes[1], (0..4).find(|&k| solid[k]).unwrap())] }
+                    // Saddle: cut off both solid corners.
+                    4 => { (0..4).filter(|&k| solid[k]).map(|k| ((k + 3) % 4, k, k)).collect() }
+                    _ => { vec![] }
+                };
+                for (m1, m2, solid_corner) in segments {
+                    let (p, q, s) = (midpoint(m1), midpoint(m2), doubled(solid_corner));
+                    if (q.0 - p.0) * (s.1 - p.1) - (q.1 - p.1) * (s.0 - p.0) > 0 {
+                        edges.insert(p, q);
+                    }
+                    else {
+                        edges.insert(q, p);
+                    }
+                }
+            }
+        }
+        let mut outlines = vec![];
+        while let Some(&start) = edges.keys().next() {
+            let mut outline = vec![];
+            let mut current = start;
+            loop {
+                let next = edges.remove(&current).unwrap();
+                outline.push(current);
+                current = next;
+                if current == start {
+                    break
+                }
+            }
+            let scale = 0.5 * self.cell_size;
+            outlines.push(remove_collinear(&outline).iter().map(|&(x, y)| Point::new(x as f64 * scale, y as f64 * scale)).collect());
+        }
+        outlines
+    }
+
+    pub fn get_convex_pieces(&self) -> Vec<Vec<Point>> {
+        // Holes are bridged into the smallest outline around them, which turns
+        // every outline into a single (weakly) simple polygon for the decomposition.
+        let outlines = self.get_outlines();
+        let areas: Vec<f64> = outlines.iter().map(|outline| signed_area(outline)).collect();
+        let mut polygons: Vec<(f64, Vec<Point>)> = vec![];
+        let mut holes: Vec<&Vec<Point>> = vec![];
+        for (outline, &area) in outlines.iter().zip(areas.iter()) {
+            if area > 0.0 {
+                polygons.push((area, outline.clone()));
+            }
+            else {
+                holes.push(outline);
+            }
+        }
+        let max_x = |outline: &Vec<Point>| outline.iter().fold(f64::MIN, |acc, v| acc.max(v.x));
+        holes.sort_by(|a, b| max_x(b).partial_cmp(&max_x(a)).unwrap());
+        for (i, hole) in holes.iter().enumerate() {
+            let index = (0..polygons.len())
+                .filter(|&i| contains(&polygons[i].1, hole[0]))
+                .min_by(|&i, &j| polygons[i].0.partial_cmp(&polygons[j].0).unwrap())
+                .expect("Cells::get_convex_pieces - hole outside of all outlines");
+            bridge(&mut polygons[index].1, hole, &holes[i + 1..]);
+        }
+        polygons.iter().flat_map(|(_, polygon)| decomposition::decompose(polygon)).collect()
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P, cell_size: f64) -> io::Result<Cells> {
+        let mut data = vec![];
+        File::open(path)?.read_to_end(&mut data)?;
+        Cells::from_netpbm(&data, cell_size)
+    }
+
+    pub fn from_netpbm(data: &[u8], cell_size: f64) -> io::Result<Cells> {
+        // Reads plain and raw PBM (P1, P4) and PGM (P2, P5) images. Black pixels are
+        // solid with a density of one, white pixels are empty and gray values of
+        // graymaps are densities in between.
+        let mut index = 0;
+        let magic = next_token(data, &mut index).ok_or_else(|| invalid_data("missing magic number"))?;
+        let width = parse_number(data, &mut index)? as usize;
+        let height = parse_number(data, &mut index)? as usize;
+        let is_bitmap = magic == "P1" || magic == "P4";
+        let max_value = if is_bitmap { 1 } else { parse_number(data, &mut index)? };
+        if max_value == 0 || max_value > 65535 {
+            return Err(invalid_data("invalid maximum value"))
+        }
+        let num_pixels = width.checked_mul(height).ok_or_else(|| invalid_data("image is too large"))?;
+        let pixels: Vec<u32> = match magic.as_str() {
+            "P1" => {
+                let mut pixels = vec![];
+                while pixels.len() < num_pixels {
+                    skip_whitespace_and_comments(data, &mut index);
+                    match data.get(index) {
+                        Some(&b'0') => { pixels.push(0) }
+                        Some(&b'1') => { pixels.push(1) }
+                        _ => { return Err(invalid_data("expected 0 or 1")) }
+                    }
+                    index += 1;
+                }
+                pixels
+            }
+            "P2" => {
+                let mut pixels = vec![];
+                for _ in 0..num_pixels {
+                    pixels.push(parse_number(data, &mut index)?);
+                }
+                pixels
+            }
+            "P4" => {
+                // A single whitespace character separates the header from the data.
+                index += 1;
+                let bytes_per_row = width.div_ceil(8);
+                let raw = data.get(index..index + bytes_per_row * height).ok_or_else(|| invalid_data("missing pixel data"))?;
+                let mut pixels = vec![];
+                for y in 0..height {
+                    for x in 0..width {
+                        pixels.push(((raw[y * bytes_per_row + x / 8] >> (7 - x % 8)) & 1) as u32);
+                    }
+                }
+                pixels
+            }
+            "P5" => {
+                index += 1;
+                let bytes_per_pixel = if max_value < 256 { 1 } else { 2 };
+                let raw = data.get(index..index + bytes_per_pixel * num_pixels).ok_or_else(|| invalid_data("missing pixel data"))?;
+                raw.chunks(bytes_per_pixel).map(|bytes| bytes.iter().fold(0, |acc, &byte| acc * 256 + byte as u32)).collect()
+            }
+            _ => { return Err(invalid_data("unsupported magic number, expected P1, P2, P4 or P5")) }
+        };
+        if pixels.iter().any(|&pixel| pixel > max_value) {
+            return Err(invalid_data("pixel value larger than the maximum value"))
+        }
+        let densities = pixels.iter().map(|&pixel| {
+            if is_bitmap { pixel as f64 } else { (max_value - pixel) as f64 / max_value as f64 }
+        }).collect();
+        Cells::from_densities(width, height, cell_size, densities).map_err(|message| invalid_data(&message))
+    }
+}
+
+impl<S: Scalar> Grid<S> {
+    // The top left corner of the cells is placed at origin.
+    pub fn new(cells: Cells, origin: Point<S>) -> Result<Grid<S>, String> {
+        if !cells.has_solid_cells() {
+            return Err("grid has no solid cells".to_string())
+        }
+        let center = Point::from_f64(cells.get_center_of_mass());
+        let pos = origin + center;
+        let pieces = cells.get_convex_pieces().into_iter().map(|piece| {
+            Polygon::new(piece.iter().map(|&vertex| Point::from_f64(vertex) + origin).collect())
+        }).collect();
+        Ok(Grid {
+            pos,
+            apos: S::zero(),
+            cells,
+            center,
+            compound: Compound::with_pos(pieces, pos),
+            surface_velocity: S::zero()
+        })
+    }
+
+    // A grid at the origin with collision geometry that was already built from the cells.
+    pub fn from_parts(cells: Cells, center: Point<S>, compound: Compound<S>) -> Result<Grid<S>, String> {
+        if !cells.has_solid_cells() {
+            return Err("grid has no solid cells".to_string())
+        }
+        Ok(Grid {
+            pos: compound.pos,
+            apos: S::zero(),
+            cells,
+            center,
+            compound,
+            surface_velocity: S::zero()
+        })
+    }
+
+    // Converts a point relative to the top left corner of the cells to world coordinates.
+    pub fn to_world(&self, local: Point<S>) -> Point<S> {
+        self.pos + (local - self.center).rotate(self.apos)
+    }
+
+    pub fn to_local(&self, world: Point<S>) -> Point<S> {
+        (world - self.pos).rotate(-self.apos) + self.center
+    }
+
+    pub fn project(&self, normal: Point<S>) -> [S;2] {
+        self.compound.project(normal)
+    }
+
+    pub fn update_pos(&mut self, pos: Point<S>, apos: S) {
+        self.pos = pos;
+        self.apos = apos;
+        self.compound.update_pos(pos, apos);
+    }
+
+    pub fn get_aabb(&self) -> Aabb<S> {
+        self.compound.get_aabb()
+    }
+
+    pub fn get_moment_of_inertia(&self) -> S {
+        S::from_f64(self.cells.get_moment_of_inertia())
+    }
+}
+
+fn remove_collinear(outline: &[(i64, i64)]) -> Vec<(i64, i64)> {
+    // Simplify an outline by removing the vertices in the middle of straight runs.
+    let num_vertices = outline.len();
+    (0..num_vertices).filter(|&i| {
+        let previous = outline[(i + num_vertices - 1) % num_vertices];
+        let vertex = outline[i];
+        let next = outline[(i + 1) % num_vertices];
+        (vertex.0 - previous.0) * (next.1 - vertex.1) - (vertex.1 - previous.1) * (next.0 - vertex.0) != 0
+    }).map(|i| outline[i]).collect()
+}
+
+fn contains(polygon: &[Point], point: Point) -> bool {
+    // Even-odd ray casting.
+    let mut inside = false;
+    for i in 0..polygon.len() {
+        let a = polygon[i];
+        let b = polygon[(i + 1) % polygon.len()];
+        if (a.y > point.y) != (b.y > point.y) && point.x < a.x + (point.y - a.y) / (b.y - a.y) * (b.x - a.x) {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+fn is_in_wedge(outline: &[Point], i: usize, direction: Point) -> bool {
+    // Whether the direction points into the solid side at vertex i of an outline.
+    let num_vertices = outline.len();
+    let incoming = outline[i] - outline[(i + num_vertices - 1) % num_vertices];
+    let outgoing = outline[(i + 1) % num_vertices] - outline[i];
+    let left_of_incoming = incoming.orth() * direction > 0.0;
+    let left_of_outgoing = outgoing.orth() * direction > 0.0;
+    if incoming.orth() * outgoing > 0.0 {
+        left_of_incoming && left_of_outgoing
+    }
+    else {
+        left_of_incoming || left_of_outgoing
+    }
+}
+
+fn bridge(polygon: &mut Vec<Point>, hole: &[Point], other_holes: &[&Vec<Point>]) {
+    // Connect the rightmost vertex of the hole to the closest vertex of the polygon
+    // that can be reached without crossing any edge of the polygon or of the holes
+    // that are not bridged yet, and walk around the hole there.
+    let hole_index = (0..hole.len()).max_by(|&i, &j| hole[i].x.partial_cmp(&hole[j].x).unwrap()).unwrap();
+    let hole_vertex = hole[hole_index];
+    let crosses = |outline: &[Point], start: Point, end: Point| {
+        (0..outline.len()).any(|k| {
+            let (a, b) = (outline[k], outline[(k + 1) % outline.len()]);
+            let touches_endpoint = a == start || a == end || b == start || b == end;
+            !touches_endpoint && segments_intersect(a, b, start, end)
+        })
+    };
+    let candidate = (0..polygon.len())
+        .filter(|&i| {
+            let vertex = polygon[i];
+            is_in_wedge(polygon, i, hole_vertex - vertex) && is_in_wedge(hole, hole_index, vertex - hole_vertex) &&
+                !crosses(polygon, hole_vertex, vertex) && !crosses(hole, hole_vertex, vertex) &&
+                !other_holes.iter().any(|other| crosses(other, hole_vertex, vertex))
+        })
+        .min_by(|&i, &j| (polygon[i] - hole_vertex).norm().partial_cmp(&(polygon[j] - hole_vertex).norm()).unwrap())
+        .expect("bridge - no visible vertex for hole");
+    let mut bridged: Vec<Point> = polygon[..candidate + 1].to_vec();
+    bridged.extend((0..hole.len() + 1).map(|k| hole[(hole_index + k) % hole.len()]));
+    bridged.push(polygon[candidate]);
+    bridged.extend_from_slice(&polygon[candidate + 1..]);
+    *polygon = bridged;
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn skip_whitespace_and_comments(data: &[u8], index: &mut usize) {
+    while *index < data.len() {
+        if data[*index] == b'#' {
+            while *index < data.len() && data[*index] != b'\n' {
+                *index += 1;
+            }
+        }
+        else if (data[*index] as char).is_whitespace() {
+            *index += 1;
+        }
+        else {
+            break
+        }
+    }
+}
+
+fn next_token(data: &[u8], index: &mut usize) -> Option<String> {
+    skip_whitespace_and_comments(data, index);
+    let start = *index;
+    while *index < data.len() && !(data[*index] as char).is_whitespace() && data[*index] != b'#' {
+        *index += 1;
+    }
+    if *index == start {
+        None
+    }
+    else {
+        Some(String::from_utf8_lossy(&data[start..*index]).into_owned())
+    }
+}
+
+fn parse_number(data: &[u8], index: &mut usize) -> io::Result<u32> {
+    let token = next_token(data, index).ok_or_else(|| invalid_data("unexpected end of file"))?;
+    token.parse().map_err(|_| invalid_data("expected a number"))
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn ring() -> Cells {
+        let mut cells = Cells::new(4, 4, 1.0);
+        for y in 0..4 {
+            for x in 0..4 {
+                if x == 0 || y == 0 || x == 3 || y == 3 {
+                    cells.set(x, y, 1.0);
+                }
+            }
+        }
+        cells
+    }
+
+    #[test]
+    fn test_mass_properties() {
+        let cells = Cells::from_densities(2, 1, 2.0, vec![1.0, 3.0]).unwrap();
+        assert!((cells.get_mass() - 16.0).abs() < 0.00001);
+        assert!((cells.get_center_of_mass() - Point::new(2.5, 1.0)).norm() < 0.00001);
+        // Two squares of size 2 at distances 1.5 and 0.5 from the center of mass.
+        let expected = (1.0 * (4.0 / 6.0 + 1.5 * 1.5) + 3.0 * (4.0 / 6.0 + 0.5 * 0.5)) / 4.0;
+        assert!((cells.get_moment_of_inertia() - expected).abs() < 0.00001);
+    }
+
+    #[test]
+    fn test_outlines_of_ring() {
+        let cells = ring();
+        let outlines = cells.get_outlines();
+        assert_eq!(outlines.len(), 2);
+        assert_eq!(outlines.iter().filter(|outline| signed_area(outline) > 0.0).count(), 1);
+        // The outlines run through the midpoints between the cell centers, so the
+        // corners are cut off.
+        let area: f64 = outlines.iter().map(|outline| signed_area(outline)).sum();
+        assert!((area - (15.5 - 3.5)).abs() < 0.00001);
+        let pieces = cells.get_convex_pieces();
+        let pieces_area: f64 = pieces.iter().map(|piece| signed_area(piece)).sum();
+        assert!((pieces_area - area).abs() < 0.00001);
+    }
+
+    #[test]
+    fn test_netpbm() {
+        let plain = b"P1\n# a comment\n3 2\n0 1 0\n1 1 1\n";
+        let cells = Cells::from_netpbm(plain, 1.0).unwrap();
+        assert_eq!(cells.densities, vec![0.0, 1.0, 0.0, 1.0, 1.0, 1.0]);
+        let raw = [b'P', b'4', b' ', b'3', b' ', b'2', b'\n', 0b0100_0000, 0b1110_0000];
+        assert_eq!(Cells::from_netpbm(&raw, 1.0).unwrap(), cells);
+        let gray = b"P2 2 1 255 0 255";
+        assert_eq!(Cells::from_netpbm(gray, 1.0).unwrap().densities, vec![1.0, 0.0]);
+        assert!(Cells::from_netpbm(b"P3 1 1 255 0 0 0", 1.0).is_err());
+        assert!(Cells::from_netpbm(b"P1 2 2 0 1", 1.0).is_err());
+    }
+
+    #[test]
+    fn test_invalid_grids() {
+        // A white image is a valid bitmap without solid cells.
+        let empty = Cells::from_netpbm(b"P1 2 1 0 0", 1.0).unwrap();
+        assert!(Grid::<f64>::new(empty, Point::new(0.0, 0.0)).is_err());
+        assert!(Cells::from_densities(2, 2, 1.0, vec![1.0; 3]).is_err());
+        assert!(Cells::from_densities(usize::MAX, 2, 1.0, vec![]).is_err());
+        assert!(Cells::from_netpbm(format!("P1 {} 2 1", usize::MAX).as_bytes(), 1.0).is_err());
+    }
+
+    #[test]
+    fn test_carve_and_split() {
+        let mut cells = Cells::from_densities(5, 2, 1.0, vec![1.0; 10]).unwrap();
+        assert_eq!(cells.get_components().len(), 1);
+        assert!(cells.carve_circle(Point::new(2.5, 1.0), 0.8));
+        assert!(!cells.carve_circle(Point::new(2.5, 1.0), 0.8));
+        let components = cells.get_components();
+        assert_eq!(components.len(), 2);
+        assert_eq!(components[0].0, Point::new(0.0, 0.0));
+        assert_eq!(components[1].0, Point::new(3.0, 0.0));
+        assert_eq!(components[1].1, Cells::from_densities(2, 2, 1.0, vec![1.0; 4]).unwrap());
+    }
+}
@@ -0,0 +1,53 @@
+use std::hash::Hasher;
+
+use scalar::Scalar;
+
+// The 64 bit FNV-1a hash. Unlike the hasher of the standard library it is not
+// randomly seeded, so it gives the same value in every run and build.
+#[derive(Debug, Clone, Copy)]
+pub struct Fnv {
+    state: u64
+}
+
+impl Fnv {
+    pub fn new() -> Fnv {
+        Fnv { state: 0xCBF2_9CE4_8422_2325 }
+    }
+
+    pub fn write_scalar<S: Scalar>(&mut self, value: S) {
+        self.write(&value.to_bits().to_le_bytes());
+    }
+}
+
+impl Default for Fnv {
+    fn default() -> Fnv {
+        Fnv::new()
+    }
+}
+
+impl Hasher for Fnv {
+    fn finish(&self) -> u64 {
+        self.state
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.state ^= u64::from(byte);
+            self.state = self.state.wrapping_mul(0x0000_0100_0000_01B3);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn test_fnv() {
+        // Reference values of FNV-1a.
+        assert_eq!(Fnv::new().finish(), 0xCBF2_9CE4_8422_2325);
+        let mut fnv = Fnv::new();
+        fnv.write(b"a");
+        assert_eq!(fnv.finish(), 0xAF63_DC4C_8601_EC8C);
+    }
+}
@@ -0,0 +1,105 @@
+use serde::{Serialize, Deserialize};
+
+use point::Point;
+use scalar::Scalar;
+use simulation::body::Body;
+use simulation::collisions::{Velocities, apply_impulse_at};
+use super::{DT, BAUMGARTE_FACTOR};
+
+// Pins two bodies together at a point, around which both can rotate freely.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RevoluteJoint<S = f64> {
+    pub body1: usize,
+    pub body2: usize,
+    // The pinned point relative to the center of each body, at an angle of zero.
+    pub anchor1: Point<S>,
+    pub anchor2: Point<S>
+}
+
+// A joint prepared for the solver, see collisions::Constraint.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct JointConstraint<S = f64> {
+    pub body1: usize,
+    pub body2: usize,
+    pub r1: Point<S>,
+    pub r2: Point<S>,
+    pub bias: Point<S>,
+    // The inverse of the symmetric effective mass matrix.
+    pub inv_k11: S,
+    pub inv_k12: S,
+    pub inv_k22: S,
+    pub mass1: S,
+    pub mass2: S,
+    pub inertia1: S,
+    pub inertia2: S,
+    pub is_static1: bool,
+    pub is_static2: bool,
+    pub impulse: Point<S>
+}
+
+impl<S: Scalar> RevoluteJoint<S> {
+    // The anchor is given in world coordinates.
+    pub fn new(index1: usize, body1: &Body<S>, index2: usize, body2: &Body<S>, anchor: Point<S>) -> RevoluteJoint<S> {
+        RevoluteJoint {
+            body1: index1,
+            body2: index2,
+            anchor1: (anchor - body1.pos).rotate(-body1.apos),
+            anchor2: (anchor - body2.pos).rotate(-body2.apos)
+        }
+    }
+}
+
+impl<S: Scalar> JointConstraint<S> {
+    // Joints between two static bodies have nothing to solve.
+    pub fn new(joint: &RevoluteJoint<S>, bodies: &[Body<S>]) -> Option<JointConstraint<S>> {
+        let body1 = &bodies[joint.body1];
+        let body2 = &bodies[joint.body2];
+        if body1.is_static && body2.is_static {
+            return None
+        }
+        let r1 = joint.anchor1.rotate(body1.apos);
+        let r2 = joint.anchor2.rotate(body2.apos);
+        // The velocity of a body at r changes by impulse / mass + a (a * impulse) / inertia
+        // with a = r.orth().
+        let (a1, a2) = (r1.orth(), r2.orth());
+        let (inv_i1, inv_i2) = (body1.inv_inertia(), body2.inv_inertia());
+        let inv_m = body1.inv_mass() + body2.inv_mass();
+        let k11 = inv_m + inv_i1 * a1.x * a1.x + inv_i2 * a2.x * a2.x;
+        let k12 = inv_i1 * a1.x * a1.y + inv_i2 * a2.x * a2.y;
+        let k22 = inv_m + inv_i1 * a1.y * a1.y + inv_i2 * a2.y * a2.y;
+        let det = k11 * k22 - k12 * k12;
+        let error = (body2.pos + r2) - (body1.pos + r1);
+        Some(JointConstraint {
+            body1: joint.body1,
+            body2: joint.body2,
+            r1,
+            r2,
+            bias: error * (S::from_f64(BAUMGARTE_FACTOR) / S::from_f64(DT)),
+            inv_k11: k22 / det,
+            inv_k12: -k12 / det,
+            inv_k22: k11 / det,
+            mass1: body1.mass,
+            mass2: body2.mass,
+            inertia1: body1.inertia,
+            inertia2: body2.inertia,
+            is_static1: body1.is_static,
+            is_static2: body2.is_static,
+            impulse: Point::zero()
+        })
+    }
+
+    // Applies the impulse that stops the anchors from moving apart and pulls them
+    // back together if they drifted.
+    pub fn resolve<V: Velocities<S> + ?Sized>(&mut self, velocities: &mut V) {
+        let relative_velocity = velocities.vel_at(self.body2, self.r2) - velocities.vel_at(self.body1, self.r1);
+        let rhs = -(relative_velocity + self.bias);
+        let p = Point::new(self.inv_k11 * rhs.x + self.inv_k12 * rhs.y, self.inv_k12 * rhs.x + self.inv_k22 * rhs.y);
+        if !self.is_static1 {
+            apply_impulse_at(velocities, self.body1, -p, self.r1, self.mass1, self.inertia1);
+        }
+        if !self.is_static2 {
+            apply_impulse_at(velocities, self.body2, p, self.r2, self.mass2, self.inertia2);
+        }
+        self.impulse += p;
+    }
+}
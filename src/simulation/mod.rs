@@ -1,7 +1,5 @@
-
-extern crate rand;
-
 use point::Point;
+use scalar::Scalar;
 
 pub mod body;
 pub mod shape;
@@ -9,66 +7,330 @@ pub mod collisions;
 pub mod collision_detection;
 pub mod polygon;
 pub mod circle;
+pub mod segment;
+pub mod capsule;
+pub mod chain;
+pub mod aabb;
+pub mod filter;
+pub mod compound;
+pub mod decomposition;
+pub mod events;
+pub mod fracture;
+pub mod grid;
+pub mod gjk;
+pub mod scene;
+pub mod random;
+pub mod hash;
+pub mod joint;
+pub mod force_field;
+pub mod replay;
+#[cfg(feature = "parallel")]
+pub mod parallel;
+
+use std::hash::Hasher;
+use std::io;
+use std::path::Path;
 
 use std::f64;
 
-const DT : f64 = 0.01;
+pub const DT : f64 = 0.01;
 const GRAVITY : f64 = 10.0;
 // const GRAVITY : f64 = 0.0;
 const GRAVITY_DIR : Point = Point{x: 0.0, y: 1.0};
 const BAUMGARTE_FACTOR : f64 = 0.2;
-const NUM_ITERATIONS: usize = 200;
+pub const NUM_ITERATIONS: usize = 200;
 const COLLISION_MARGIN: f64 = 0.05;
 const ALLOWED_PENETRATION: f64 = 0.02;
 // const COLLISION_MARGIN: f64 = 0.0;
+// Friction and restitution of new bodies.
 const FRICTION: f64 = 1.0;
+const RESTITUTION: f64 = 0.0;
+// Contacts that approach more slowly than this do not bounce, so that resting
+// bodies with restitution come to rest.
+const RESTITUTION_THRESHOLD: f64 = 1.0;
+const ONE_WAY_VELOCITY_TOLERANCE: f64 = 0.01;
+// Number of Voronoi seeds when a polygon breaks.
+const NUM_SHARDS: usize = 8;
+// Shards with a smaller area do not break any further.
+const MIN_BREAKING_AREA: f64 = 0.05;
+// Maximum sine of the angle between two edges that are treated as parallel.
+const PARALLEL_TOLERANCE: f64 = 0.01;
+// Amount by which an axis of the second shape needs to separate more than one of
+// the first shape before it is chosen as the reference, to avoid flip-flopping.
+const AXIS_TOLERANCE: f64 = 0.001;
+// Maximum deviation of a unit normal from a face normal that still counts as the face normal.
+const FACE_TOLERANCE: f64 = 0.000001;
+// Distances closer than this to each other are treated as equal.
+const DISTANCE_TOLERANCE: f64 = 0.000001;
+// Spring constant and damping per unit mass of the mouse joint, see Simulation::drag_body.
+const MOUSE_STIFFNESS: f64 = 100.0;
+const MOUSE_DAMPING: f64 = 20.0;
 
-pub struct Simulation {
-    pub bodies : Vec<body::Body>,
-    pub collision_handler : collisions::CollisionHandler
+pub struct Simulation<S = f64> {
+    pub bodies : Vec<body::Body<S>>,
+    pub joints : Vec<joint::RevoluteJoint<S>>,
+    pub collision_handler : collisions::CollisionHandler<S>,
+    pub events : Vec<events::Event<S>>,
+    pub gravity : Point<S>,
+    pub force_fields : Vec<force_field::ForceField<S>>
 }
 
-impl Simulation {
+// A copy of the complete state of a simulation, see Simulation::snapshot.
+#[derive(Debug, Clone)]
+pub struct Snapshot<S: Scalar = f64> {
+    bodies: Vec<body::Body<S>>,
+    joints: Vec<joint::RevoluteJoint<S>>,
+    collisions: collisions::CollisionCache<S>,
+    events: Vec<events::Event<S>>,
+    gravity: Point<S>,
+    force_fields: Vec<force_field::ForceField<S>>
+}
+
+impl<S: Scalar> Simulation<S> {
     pub fn timestep(&mut self) {
+        self.events.clear();
         self.handle_gravity();
-        self.collision_handler.timestep(&mut self.bodies);
+        self.collision_handler.timestep(&mut self.bodies, &self.joints, &mut self.events);
         self.integrate();
+        self.handle_fractures();
+    }
+
+    fn handle_fractures(&mut self) {
+        let impulses = fracture::get_impulses(&self.collision_handler.collisions);
+        for (&index, &(impulse, impact)) in impulses.iter() {
+            let is_breaking = match (self.bodies[index].breaking_impulse, &self.bodies[index].shape) {
+                (Some(breaking_impulse), &shape::Shape::Polygon(_)) => { impulse > breaking_impulse }
+                _ => { false }
+            };
+            if is_breaking {
+                let children = self.fracture(index, impact);
+                self.events.push(events::Event::BodyBroken { parent: index, children });
+            }
+        }
+    }
+
+    // Splits a polygon body into Voronoi shards around the impact point. The
+    // first shard keeps the index of the body. Returns the indices of the shards.
+    // The pairs and joints of the body are dropped as if it had been removed.
+    pub fn fracture(&mut self, index: usize, impact: Point<S>) -> Vec<usize> {
+        let (vertices, radius) = match self.bodies[index].shape {
+            shape::Shape::Polygon(ref polygon) => { (polygon.vertices.clone(), polygon.radius) }
+            _ => { panic!("Simulation::fracture - body is not a polygon") }
+        };
+        let shards: Vec<body::Body<S>> = fracture::voronoi_shards(&vertices, &fracture::get_seeds(impact, &vertices), radius).into_iter().map(|shard| {
+            body::get_shard(&self.bodies[index], shard)
+        }).collect();
+        self.forget_body(index);
+        let mut indices = vec![];
+        for (i, shard) in shards.into_iter().enumerate() {
+            if i == 0 {
+                self.bodies[index] = shard;
+                indices.push(index);
+            }
+            else {
+                self.bodies.push(shard);
+                indices.push(self.bodies.len() - 1);
+            }
+        }
+        indices
+    }
+
+    pub fn snapshot(&self) -> Snapshot<S> {
+        Snapshot {
+            bodies: self.bodies.clone(),
+            joints: self.joints.clone(),
+            collisions: self.collision_handler.get_cache(),
+            events: self.events.clone(),
+            gravity: self.gravity,
+            force_fields: self.force_fields.clone()
+        }
+    }
+
+    // Brings the simulation back to the state of the snapshot, after which it steps
+    // exactly like it did after the snapshot was taken. The pre-solve hook is kept.
+    pub fn restore(&mut self, snapshot: &Snapshot<S>) {
+        self.bodies = snapshot.bodies.clone();
+        self.joints = snapshot.joints.clone();
+        self.collision_handler.set_cache(&snapshot.collisions);
+        self.events = snapshot.events.clone();
+        self.gravity = snapshot.gravity;
+        self.force_fields = snapshot.force_fields.clone();
+    }
+
+    // A hash of the positions and velocities of all bodies, for checking that two
+    // runs are identical.
+    pub fn get_state_hash(&self) -> u64 {
+        let mut hash = hash::Fnv::new();
+        hash.write(&(self.bodies.len() as u64).to_le_bytes());
+        for body in self.bodies.iter() {
+            for &value in [body.pos.x, body.pos.y, body.apos, body.vel.x, body.vel.y, body.avel].iter() {
+                hash.write_scalar(value);
+            }
+        }
+        hash.finish()
+    }
+
+    pub fn drain_events(&mut self) -> std::vec::Drain<'_, events::Event<S>> {
+        self.events.drain(..)
+    }
+
+    // Distance and closest points between the shapes of two bodies.
+    pub fn distance(&self, body1: usize, body2: usize) -> gjk::DistanceResult<S> {
+        collision_detection::distance(&self.bodies[body1], &self.bodies[body2])
+    }
+
+    // Mouse joint: pulls the point anchor (relative to the center of the body, at
+    // an angle of zero) towards target with a damped spring for the next step.
+    pub fn drag_body(&mut self, index: usize, anchor: Point<S>, target: Point<S>) {
+        let body = &mut self.bodies[index];
+        let offset = anchor.rotate(body.apos);
+        let stretch = target - (body.pos + offset);
+        let force = (stretch * S::from_f64(MOUSE_STIFFNESS) - body.vel_at(offset) * S::from_f64(MOUSE_DAMPING)) * body.mass;
+        body.apply_force_at(force, offset);
+    }
+
+    // Pins two bodies together at the (world) position anchor. Returns the index
+    // of the joint.
+    pub fn add_revolute_joint(&mut self, body1: usize, body2: usize, anchor: Point<S>) -> usize {
+        let joint = joint::RevoluteJoint::new(body1, &self.bodies[body1], body2, &self.bodies[body2], anchor);
+        self.joints.push(joint);
+        self.joints.len() - 1
+    }
+
+    // Removes a body and its joints. The indices of the bodies after it move down by one.
+    pub fn remove_body(&mut self, index: usize) -> body::Body<S> {
+        self.collision_handler.remove_body(index);
+        let shift = |body: usize| if body > index { body - 1 } else { body };
+        self.joints.retain(|joint| joint.body1 != index && joint.body2 != index);
+        for joint in self.joints.iter_mut() {
+            joint.body1 = shift(joint.body1);
+            joint.body2 = shift(joint.body2);
+        }
+        self.bodies.remove(index)
+    }
+
+    // Empties the cells of a grid body whose centers are within radius of the
+    // (world) position center. Other bodies are not changed. See split_grid for
+    // the returned indices.
+    pub fn carve_circle(&mut self, index: usize, center: Point<S>, radius: S) -> Vec<usize> {
+        let cells = match self.bodies[index].shape {
+            shape::Shape::Grid(ref grid) => {
+                let mut cells = grid.cells.clone();
+                if !cells.carve_circle(grid.to_local(center).to_f64(), radius.to_f64()) {
+                    return vec![index]
+                }
+                cells
+            }
+            _ => { return vec![index] }
+        };
+        self.split_grid(index, cells)
+    }
+
+    // Sets the density of a cell of a grid body. Like carve_circle it leaves other
+    // bodies and cells outside of the grid alone. See split_grid for the returned
+    // indices.
+    pub fn set_cell(&mut self, index: usize, x: usize, y: usize, density: f64) -> Vec<usize> {
+        let cells = match self.bodies[index].shape {
+            shape::Shape::Grid(ref grid) => {
+                if x >= grid.cells.width || y >= grid.cells.height {
+                    return vec![index]
+                }
+                let mut cells = grid.cells.clone();
+                cells.set(x, y, density);
+                cells
+            }
+            _ => { return vec![index] }
+        };
+        self.split_grid(index, cells)
+    }
+
+    // Replaces a grid body by one body per connected part of the new cells. The
+    // first part keeps the index of the body, the others are appended. Returns the
+    // indices of the parts. If no cells are left the body is removed. Like in
+    // fracture, the pairs and joints of the body are dropped.
+    fn split_grid(&mut self, index: usize, cells: grid::Cells) -> Vec<usize> {
+        let fragments: Vec<body::Body<S>> = cells.get_components().into_iter().map(|(offset, component)| {
+            body::get_grid_fragment(&self.bodies[index], offset, component)
+        }).collect();
+        if fragments.is_empty() {
+            self.remove_body(index);
+            return vec![]
+        }
+        self.forget_body(index);
+        let mut indices = vec![];
+        for (i, fragment) in fragments.into_iter().enumerate() {
+            if i == 0 {
+                self.bodies[index] = fragment;
+                indices.push(index);
+            }
+            else {
+                self.bodies.push(fragment);
+                indices.push(self.bodies.len() - 1);
+            }
+        }
+        indices
+    }
+
+    // Drops the pairs and joints of a body that is replaced by another one at the
+    // same index.
+    fn forget_body(&mut self, index: usize) {
+        self.collision_handler.forget_body(index);
+        self.joints.retain(|joint| joint.body1 != index && joint.body2 != index);
     }
 
     pub fn integrate(&mut self) {
         for body in self.bodies.iter_mut() {
-            body.timestep(DT);
+            body.timestep(S::from_f64(DT));
         }
     }
 
     fn handle_gravity(&mut self) {
         for body in self.bodies.iter_mut() {
-            apply_gravity(body);
+            apply_gravity(body, self.gravity);
+            for field in self.force_fields.iter() {
+                field.apply(body);
+            }
         }
     }
 
-    pub fn new(bodies: Vec<body::Body>) -> Simulation {
+    pub fn new(bodies: Vec<body::Body<S>>) -> Simulation<S> {
         Simulation{
-            bodies: bodies,
-            collision_handler : collisions::CollisionHandler::new()
+            bodies,
+            joints: vec![],
+            collision_handler : collisions::CollisionHandler::new(),
+            events: vec![],
+            gravity: Point::from_f64(GRAVITY * GRAVITY_DIR),
+            force_fields: vec![]
         }
     }
 }
 
-fn apply_gravity(body : &mut body::Body) {
+// Scene files store f64 values.
+impl Simulation {
+
+    pub fn from_scene_file<P: AsRef<Path>>(path: P) -> io::Result<Simulation> {
+        scene::Scene::load(path)?.to_simulation()
+    }
+
+    pub fn to_scene_file<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        scene::Scene::from_simulation(self).save(path)
+    }
+}
+
+fn apply_gravity<S: Scalar>(body : &mut body::Body<S>, gravity: Point<S>) {
     // let force = GRAVITY * body.mass * Point{x: 0.0, y: 1.0};
     // let force = GRAVITY * body.mass * (Point::new(0.0, 0.0) - body.pos);
-    let force = GRAVITY * body.mass * GRAVITY_DIR;
+    let force = gravity * (body.gravity_scale * body.mass);
     body.apply_force(force);
 }
 
 pub fn test_collision_1() -> Simulation {
-    let mut bodies : Vec<body::Body> = vec![];
-    bodies.push(body::get_rectangle(Point::new(0.5, 0.5), 1.0, 1.0, 1.0));
-    bodies.push(body::get_rectangle(Point::new(0.5, 2.5), 1.0, 1.0, 0.0));
+    let mut bodies : Vec<body::Body> = vec![
+        body::get_rectangle(Point::new(0.5, 0.5), 1.0, 1.0, 1.0),
+        body::get_rectangle(Point::new(0.5, 2.5), 1.0, 1.0, 0.0)
+    ];
     bodies[0].apos = 1.0;
-    let mut sim = Simulation::new(bodies);
-    sim
+    Simulation::new(bodies)
 }
 
 pub fn test_collision_2() -> Simulation {
@@ -82,8 +344,7 @@ pub fn test_collision_2() -> Simulation {
         bodies.push(body::get_regular_polygon(Point::new(x, y), radius, 3+i, mass));
     }
     bodies.push(body::get_rectangle(Point::new(0.0, 10.0), 30.0, 3.0, 0.0));
-    let mut sim = Simulation::new(bodies);
-    sim
+    Simulation::new(bodies)
 }
 
 pub fn test_collision_3() -> Simulation {
@@ -100,8 +361,7 @@ pub fn test_collision_3() -> Simulation {
     bodies.push(body::get_rectangle(Point::new(0.0, 10.0), 30.0, 1.0, 0.0));
     bodies.push(body::get_rectangle(Point::new(-5.0, 0.0), 1.0, 30.0, 0.0));
     bodies.push(body::get_rectangle(Point::new(5.0, 0.0), 1.0, 30.0, 0.0));
-    let mut sim = Simulation::new(bodies);
-    sim
+    Simulation::new(bodies)
 }
 
 pub fn test_collision_4() -> Simulation {
@@ -114,6 +374,529 @@ pub fn test_collision_4() -> Simulation {
     bodies.push(body::get_rectangle(Point::new(0.0, 10.0), 30.0, 1.0, 0.0));
     bodies.push(body::get_rectangle(Point::new(-5.0, 0.0), 1.0, 30.0, 0.0));
     bodies.push(body::get_rectangle(Point::new(5.0, 0.0), 1.0, 30.0, 0.0));
+    Simulation::new(bodies)
+}
+
+// Random polygons falling into a box. The same seed always gives the same scene.
+pub fn test_random_polygons(seed: u64, num_polygons: usize) -> Simulation {
+    let mut random = random::Random::new(seed);
+    let mut bodies : Vec<body::Body> = vec![];
+    for i in 0..num_polygons {
+        let x = random.range(-4.0, 4.0);
+        let y = 5.0 - (i as f64) * 1.5;
+        let radius = random.range(0.3, 0.8);
+        let num_vertices = random.range_usize(3, 8);
+        bodies.push(body::get_regular_polygon(Point::new(x, y), radius, num_vertices, radius * radius));
+    }
+    bodies.push(body::get_rectangle(Point::new(0.0, 10.0), 30.0, 1.0, 0.0));
+    bodies.push(body::get_rectangle(Point::new(-5.0, 0.0), 1.0, 30.0, 0.0));
+    bodies.push(body::get_rectangle(Point::new(5.0, 0.0), 1.0, 30.0, 0.0));
+    Simulation::new(bodies)
+}
+
+// A pyramid of boxes on the ground with num_rows boxes in its bottom row.
+pub fn test_pyramid(num_rows: usize) -> Simulation {
+    let mut bodies : Vec<body::Body> = vec![body::get_rectangle(Point::new(0.0, 10.0), 2.0 * num_rows as f64 + 10.0, 1.0, 0.0)];
+    for row in 0..num_rows {
+        let num_boxes = num_rows - row;
+        for i in 0..num_boxes {
+            let x = (i as f64 - (num_boxes - 1) as f64 / 2.0) * 1.05;
+            bodies.push(body::get_rectangle(Point::new(x, 9.0 - row as f64), 1.0, 1.0, 1.0));
+        }
+    }
+    Simulation::new(bodies)
+}
+
+// Like test_collision_4 with any number of boxes, between walls that are as high
+// as the stack.
+pub fn test_stack(num_boxes: usize) -> Simulation {
+    let mut bodies : Vec<body::Body> = vec![];
+    for i in 0..num_boxes {
+        bodies.push(body::get_rectangle(Point::new(0.5, 8.5 - 1.4 * (i as f64)), 1.0, 1.0, 1.0));
+    }
+    let height = 1.4 * num_boxes as f64 + 10.0;
+    bodies.push(body::get_rectangle(Point::new(0.0, 10.0), 30.0, 1.0, 0.0));
+    bodies.push(body::get_rectangle(Point::new(-5.0, 10.0 - height / 2.0), 1.0, height, 0.0));
+    bodies.push(body::get_rectangle(Point::new(5.0, 10.0 - height / 2.0), 1.0, height, 0.0));
+    Simulation::new(bodies)
+}
+
+// Random polygons on a grid above a wide container, 50 per row.
+pub fn test_polygon_pile(seed: u64, num_polygons: usize) -> Simulation {
+    let mut random = random::Random::new(seed);
+    let mut bodies : Vec<body::Body> = vec![];
+    for i in 0..num_polygons {
+        let x = ((i % 50) as f64 - 24.5) * 1.8 + random.range(-0.1, 0.1);
+        let y = 8.7 - (i / 50) as f64 * 1.8;
+        let radius = random.range(0.3, 0.8);
+        let num_vertices = random.range_usize(3, 8);
+        bodies.push(body::get_regular_polygon(Point::new(x, y), radius, num_vertices, radius * radius));
+    }
+    let height = 1.8 * (num_polygons / 50) as f64 + 10.0;
+    bodies.push(body::get_rectangle(Point::new(0.0, 10.0), 100.0, 1.0, 0.0));
+    bodies.push(body::get_rectangle(Point::new(-50.0, 10.0 - height / 2.0), 1.0, height, 0.0));
+    bodies.push(body::get_rectangle(Point::new(50.0, 10.0 - height / 2.0), 1.0, height, 0.0));
+    Simulation::new(bodies)
+}
+
+// Circles on a grid above a container, 20 per row.
+pub fn test_circle_pile(num_circles: usize) -> Simulation {
+    let mut bodies : Vec<body::Body> = vec![];
+    for i in 0..num_circles {
+        let x = ((i % 20) as f64 - 9.5) * 1.1 + 0.05 * ((i / 20) % 2) as f64;
+        let y = 8.0 - (i / 20) as f64 * 1.1;
+        bodies.push(body::get_circle(Point::new(x, y), 1.0, 0.5));
+    }
+    let height = 1.1 * (num_circles / 20) as f64 + 10.0;
+    bodies.push(body::get_rectangle(Point::new(0.0, 10.0), 30.0, 1.0, 0.0));
+    bodies.push(body::get_rectangle(Point::new(-12.0, 10.0 - height / 2.0), 1.0, height, 0.0));
+    bodies.push(body::get_rectangle(Point::new(12.0, 10.0 - height / 2.0), 1.0, height, 0.0));
+    Simulation::new(bodies)
+}
+
+// A horizontal chain of thin boxes linked by revolute joints, hanging from a
+// static body at one end. The links do not collide with each other.
+pub fn test_chain(num_links: usize) -> Simulation {
+    let mut filter = filter::CollisionFilter::new();
+    filter.group_index = -1;
+    let mut bodies : Vec<body::Body> = vec![body::get_rectangle(Point::new(0.0, 0.0), 0.2, 0.2, 0.0)];
+    for i in 0..num_links {
+        bodies.push(body::get_rectangle(Point::new(i as f64 + 0.5, 0.0), 1.0, 0.2, 1.0));
+    }
+    for body in bodies.iter_mut() {
+        body.filter = filter;
+    }
     let mut sim = Simulation::new(bodies);
+    for i in 0..num_links {
+        sim.add_revolute_joint(i, i + 1, Point::new(i as f64, 0.0));
+    }
     sim
 }
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use fixed::Fixed;
+
+    fn boxes_on_ground<S: Scalar>() -> Simulation<S> {
+        let s = S::from_f64;
+        let mut bodies = vec![body::get_rectangle(Point::new(s(0.0), s(10.0)), s(30.0), s(1.0), s(0.0))];
+        for i in 0..5 {
+            bodies.push(body::get_rectangle(Point::new(s(0.3 * i as f64), s(8.0 - 1.2 * i as f64)), s(1.0), s(1.0), s(1.0)));
+        }
+        Simulation::new(bodies)
+    }
+
+    #[test]
+    fn test_sensor_events() {
+        let mut sensor = body::get_rectangle(Point::new(0.0, 5.0), 4.0, 1.0, 0.0);
+        sensor.is_sensor = true;
+        let mut sim = Simulation::new(vec![
+            body::get_rectangle(Point::new(0.0, 10.0), 30.0, 1.0, 0.0),
+            sensor,
+            body::get_rectangle(Point::new(0.0, 2.0), 1.0, 1.0, 1.0)
+        ]);
+        let mut events = vec![];
+        for _ in 0..200 {
+            sim.timestep();
+            events.extend(sim.drain_events().filter(|event| match *event {
+                events::Event::SensorEnter { .. } | events::Event::SensorExit { .. } => { true }
+                events::Event::ContactBegin(ref contact) => { contact.body1 == 1 || contact.body2 == 1 }
+                _ => { false }
+            }));
+        }
+        // The box falls through the sensor without touching it and lands on the ground.
+        assert_eq!(events, vec![events::Event::SensorEnter { body1: 1, body2: 2 }, events::Event::SensorExit { body1: 1, body2: 2 }]);
+        assert!((sim.bodies[2].pos.y - 9.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn test_breaking_impulse() {
+        let run = |breaking_impulse: f64| {
+            let mut falling = body::get_rounded_polygon(vec![Point::new(-0.5, 1.5), Point::new(0.5, 1.5), Point::new(0.5, 2.5), Point::new(-0.5, 2.5)], 0.05, 1.0);
+            falling.vel = Point::new(0.0, 20.0);
+            falling.breaking_impulse = Some(breaking_impulse);
+            let mut sim = Simulation::new(vec![body::get_rectangle(Point::new(0.0, 10.0), 30.0, 1.0, 0.0), falling]);
+            let mut broken = vec![];
+            for _ in 0..100 {
+                sim.timestep();
+                broken.extend(sim.drain_events().filter_map(|event| match event {
+                    events::Event::BodyBroken { parent, children } => { Some((parent, children)) }
+                    _ => { None }
+                }));
+            }
+            (sim, broken)
+        };
+        let (sim, broken) = run(1000.0);
+        assert!(broken.is_empty());
+        assert_eq!(sim.bodies.len(), 2);
+        let (sim, broken) = run(5.0);
+        assert_eq!(broken[0].0, 1);
+        assert!(broken[0].1.len() > 1);
+        // The breaking impulse of the shards is their share of the one of the
+        // parent, like their mass, unless they are too small to break.
+        for body in sim.bodies[1..].iter() {
+            let is_small = match body.shape {
+                shape::Shape::Polygon(ref polygon) => { polygon.get_area() < MIN_BREAKING_AREA }
+                _ => { panic!("test_breaking_impulse - shard is not a polygon") }
+            };
+            assert_eq!(body.breaking_impulse.is_none(), is_small);
+            assert!(body.breaking_impulse.is_none_or(|breaking_impulse| (breaking_impulse - 5.0 * body.mass).abs() < 0.00001));
+            assert_eq!(body.shape.get_radius(), 0.05);
+        }
+        // The rounded shards touch without overlapping.
+        let mut sim = Simulation::new(vec![body::get_rounded_polygon(vec![Point::new(-0.5, 1.5), Point::new(0.5, 1.5), Point::new(0.5, 2.5), Point::new(-0.5, 2.5)], 0.05, 1.0)]);
+        let shards = sim.fracture(0, Point::new(0.0, 2.5));
+        for &i in shards.iter() {
+            for &j in shards.iter().filter(|&&j| j > i) {
+                assert!(collision_detection::find_collisions(&sim.bodies[i], &sim.bodies[j]).iter().all(|collision| collision.depth < 0.00001));
+            }
+        }
+    }
+
+    #[test]
+    fn test_fracture_forgets_pairs_and_joints() {
+        let mut sim = Simulation::new(vec![
+            body::get_rectangle(Point::new(0.0, 10.0), 30.0, 1.0, 0.0),
+            body::get_rectangle(Point::new(0.0, 9.0), 1.0, 1.0, 1.0),
+            body::get_rectangle(Point::new(2.0, 9.0), 1.0, 1.0, 1.0)
+        ]);
+        sim.add_revolute_joint(1, 2, Point::new(1.0, 9.0));
+        for _ in 0..10 {
+            sim.timestep();
+        }
+        assert!(sim.collision_handler.collisions.iter().any(|collision| collision.body2 == 1));
+        let shards = sim.fracture(1, Point::new(0.0, 9.5));
+        assert!(sim.joints.is_empty());
+        assert!(sim.collision_handler.collisions.iter().all(|collision| collision.body1 != 1 && collision.body2 != 1));
+        // The first shard starts its own contact with the ground instead of
+        // continuing the one of the box.
+        sim.timestep();
+        let events: Vec<events::Event> = sim.drain_events().collect();
+        assert!(events.iter().any(|event| match *event {
+            events::Event::ContactBegin(ref contact) => { (contact.body1, contact.body2) == (0, shards[0]) }
+            _ => { false }
+        }));
+        assert!(events.iter().all(|event| match *event {
+            events::Event::ContactPersist(ref contact) => { contact.body2 != 1 }
+            _ => { true }
+        }));
+    }
+
+    #[test]
+    fn test_split_grid() {
+        let mut cells = grid::Cells::new(3, 1, 1.0);
+        for x in 0..3 {
+            cells.set(x, 0, 1.0);
+        }
+        let mut grid_body = body::get_grid(Point::new(0.0, 0.0), cells, false).unwrap();
+        grid_body.vel = Point::new(1.0, -2.0);
+        grid_body.avel = 0.5;
+        let mut sim = Simulation::new(vec![body::get_circle(Point::new(0.0, 5.0), 1.0, 0.5), grid_body]);
+        let parent = sim.bodies[1].clone();
+        assert_eq!(sim.carve_circle(0, Point::new(0.0, 5.0), 1.0), vec![0]);
+        assert_eq!(sim.set_cell(1, 3, 0, 0.0), vec![1]);
+        // Removing the middle cell leaves two fragments that move with the parent.
+        assert_eq!(sim.set_cell(1, 1, 0, 0.0), vec![1, 2]);
+        for fragment in sim.bodies[1..].iter() {
+            assert!((fragment.vel - parent.vel_at(fragment.pos - parent.pos)).norm() < 0.00001);
+            assert_eq!(fragment.avel, parent.avel);
+        }
+        assert!(sim.bodies[1].vel.y < -2.0 && sim.bodies[2].vel.y > -2.0);
+    }
+
+    #[test]
+    fn test_fracture_under_load() {
+        // A weak box under a heavy one breaks into shards that stop breaking once
+        // they are small, instead of breaking again in every step.
+        let mut weak = body::get_rectangle(Point::new(0.0, 9.0), 1.0, 1.0, 1.0);
+        weak.breaking_impulse = Some(0.2);
+        let mut sim = Simulation::new(vec![
+            body::get_rectangle(Point::new(0.0, 10.0), 30.0, 1.0, 0.0),
+            weak,
+            body::get_rectangle(Point::new(0.0, 7.5), 2.0, 2.0, 50.0)
+        ]);
+        let mut num_bodies = vec![];
+        for _ in 0..100 {
+            sim.timestep();
+            num_bodies.push(sim.bodies.len());
+        }
+        assert!(sim.bodies.len() > 3);
+        assert!(sim.bodies.len() <= 3 + (1.0 / MIN_BREAKING_AREA) as usize * NUM_SHARDS);
+        assert!(num_bodies[50..].iter().all(|&num| num == sim.bodies.len()));
+    }
+
+    #[test]
+    fn test_contact_events() {
+        let mut sim = Simulation::new(vec![
+            body::get_rectangle(Point::new(0.0, 10.0), 30.0, 1.0, 0.0),
+            body::get_rectangle(Point::new(0.0, 9.0), 1.0, 1.0, 1.0)
+        ]);
+        let mut kinds = vec![];
+        let mut step = |sim: &mut Simulation| {
+            sim.timestep();
+            for event in sim.drain_events() {
+                match event {
+                    events::Event::ContactBegin(contact) => { kinds.push(("begin", contact.body1, contact.body2)) }
+                    events::Event::ContactPersist(contact) => { kinds.push(("persist", contact.body1, contact.body2)) }
+                    events::Event::ContactEnd { body1, body2 } => { kinds.push(("end", body1, body2)) }
+                    _ => {}
+                }
+            }
+        };
+        for _ in 0..10 {
+            step(&mut sim);
+        }
+        // Lift the box off the ground.
+        sim.bodies[1] = body::get_rectangle(Point::new(0.0, 0.0), 1.0, 1.0, 1.0);
+        step(&mut sim);
+        step(&mut sim);
+        // The contact begins once, persists while the box rests and ends once.
+        assert!(kinds.len() > 5);
+        assert_eq!(kinds[0], ("begin", 0, 1));
+        assert!(kinds[1..kinds.len() - 1].iter().all(|&kind| kind == ("persist", 0, 1)));
+        assert_eq!(kinds[kinds.len() - 1], ("end", 0, 1));
+    }
+
+    #[test]
+    fn test_pre_solve_disables_contact() {
+        let mut sim = Simulation::new(vec![
+            body::get_rectangle(Point::new(0.0, 10.0), 30.0, 1.0, 0.0),
+            body::get_rectangle(Point::new(0.0, 9.0), 1.0, 1.0, 1.0),
+            body::get_rectangle(Point::new(3.0, 9.0), 1.0, 1.0, 1.0)
+        ]);
+        // Only the second box falls through the ground.
+        sim.collision_handler.set_pre_solve(|_, body2, collision| {
+            if body2.pos.x > 2.0 {
+                collision.enabled = false;
+            }
+        });
+        for _ in 0..100 {
+            sim.timestep();
+            for collision in sim.collision_handler.collisions.iter().filter(|collision| collision.body2 == 2) {
+                assert!(!collision.enabled);
+                assert_eq!(collision.normal_impulse, 0.0);
+            }
+        }
+        assert!((sim.bodies[1].pos.y - 9.0).abs() < 0.05);
+        assert!(sim.bodies[2].pos.y > 11.0);
+    }
+
+    fn one_way_platform() -> Simulation {
+        let mut platform = body::get_rectangle(Point::new(0.0, 5.0), 4.0, 0.2, 0.0);
+        platform.one_way_direction = Some(Point::new(0.0, -1.0));
+        Simulation::new(vec![body::get_rectangle(Point::new(0.0, 10.0), 30.0, 1.0, 0.0), platform])
+    }
+
+    #[test]
+    fn test_one_way_pass_through_from_below() {
+        let mut sim = one_way_platform();
+        let mut jumper = body::get_rectangle(Point::new(0.0, 8.0), 1.0, 1.0, 1.0);
+        jumper.vel = Point::new(0.0, -10.0);
+        sim.bodies.push(jumper);
+        for _ in 0..300 {
+            sim.timestep();
+        }
+        // The box jumps through the platform from below and lands on top of it.
+        assert!((sim.bodies[2].pos.y - 4.4).abs() < 0.05);
+    }
+
+    #[test]
+    fn test_one_way_resting_on_top() {
+        let mut sim = one_way_platform();
+        sim.bodies.push(body::get_rectangle(Point::new(0.0, 4.4), 1.0, 1.0, 1.0));
+        for _ in 0..100 {
+            sim.timestep();
+        }
+        assert!((sim.bodies[2].pos.y - 4.4).abs() < 0.05);
+        // A push away from the platform that is too weak to lift the box off it
+        // does not let it fall through.
+        sim.bodies[2].vel = Point::new(0.0, -0.5);
+        for _ in 0..200 {
+            sim.timestep();
+        }
+        assert!((sim.bodies[2].pos.y - 4.4).abs() < 0.05);
+    }
+
+    #[test]
+    fn test_friction_in_both_directions() {
+        let mut sim = Simulation::new(vec![
+            body::get_rectangle(Point::new(0.0, 10.0), 30.0, 1.0, 0.0),
+            body::get_rectangle(Point::new(-3.0, 9.0), 1.0, 1.0, 1.0),
+            body::get_rectangle(Point::new(3.0, 9.0), 1.0, 1.0, 1.0)
+        ]);
+        sim.bodies[1].vel = Point::new(-2.0, 0.0);
+        sim.bodies[2].vel = Point::new(2.0, 0.0);
+        for _ in 0..100 {
+            sim.timestep();
+        }
+        // Both boxes slide to a stop, whichever way they move.
+        assert!(sim.bodies[1].vel.norm() < 0.01 && sim.bodies[1].pos.x > -3.5);
+        assert!(sim.bodies[2].vel.norm() < 0.01 && sim.bodies[2].pos.x < 3.5);
+    }
+
+    #[test]
+    fn test_conveyor() {
+        let mut belt = body::get_rectangle(Point::new(0.0, 10.0), 30.0, 1.0, 0.0);
+        belt.shape.set_surface_velocity(2.0);
+        let mut sim = Simulation::new(vec![belt, body::get_rectangle(Point::new(0.0, 9.0), 1.0, 1.0, 1.0)]);
+        for _ in 0..100 {
+            sim.timestep();
+        }
+        // The box is carried along at the speed of the surface.
+        assert!((sim.bodies[1].vel - Point::new(2.0, 0.0)).norm() < 0.01);
+        assert!(sim.bodies[1].pos.x > 1.0);
+    }
+
+    #[test]
+    fn test_friction_per_body() {
+        let mut ice = body::get_rectangle(Point::new(0.0, 9.0), 1.0, 1.0, 1.0);
+        ice.friction = 0.0;
+        ice.vel = Point::new(2.0, 0.0);
+        let mut sim = Simulation::new(vec![body::get_rectangle(Point::new(0.0, 10.0), 30.0, 1.0, 0.0), ice]);
+        for _ in 0..100 {
+            sim.timestep();
+        }
+        // Without friction on one side the contact has none and the box keeps sliding.
+        assert!((sim.bodies[1].vel.x - 2.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_restitution() {
+        let bounce = |restitution: f64| {
+            let mut ball = body::get_circle(Point::new(0.0, 5.0), 1.0, 0.5);
+            ball.restitution = restitution;
+            let mut sim = Simulation::new(vec![body::get_rectangle(Point::new(0.0, 10.0), 30.0, 1.0, 0.0), ball]);
+            (0..150).map(|_| { sim.timestep(); -sim.bodies[1].vel.y }).fold(0.0, f64::max)
+        };
+        // The ball hits the ground at about 9 units per second and leaves it with
+        // the share restitution of that speed.
+        assert!(bounce(0.0) < 0.5);
+        assert!((bounce(0.5) - 4.5).abs() < 0.5);
+    }
+
+    #[test]
+    fn test_force_fields() {
+        let mut sim = Simulation::new(vec![
+            body::get_circle(Point::new(-3.0, 0.0), 1.0, 0.5),
+            body::get_circle(Point::new(20.0, 0.0), 1.0, 0.5)
+        ]);
+        sim.gravity = Point::zero();
+        sim.bodies[1].gravity_scale = 0.0;
+        sim.force_fields = vec![
+            force_field::ForceField::Uniform { acceleration: Point::new(1.0, 0.0) },
+            force_field::ForceField::Radial { center: Point::new(0.0, 0.0), strength: 2.0, radius: 5.0 }
+        ];
+        for _ in 0..50 {
+            sim.timestep();
+        }
+        // The first body is inside the radial field and also pulled toward its center.
+        assert!((sim.bodies[0].vel - Point::new(1.5, 0.0)).norm() < 0.00001);
+        assert!((sim.bodies[1].vel - Point::new(0.5, 0.0)).norm() < 0.00001);
+    }
+
+    #[test]
+    fn test_surface_velocity_per_shape() {
+        // Two belts of one compound body that run towards each other.
+        let mut left = polygon::Polygon::new(vec![Point::new(-10.0, 9.5), Point::new(0.0, 9.5), Point::new(0.0, 10.5), Point::new(-10.0, 10.5)]);
+        let mut right = polygon::Polygon::new(vec![Point::new(0.0, 9.5), Point::new(10.0, 9.5), Point::new(10.0, 10.5), Point::new(0.0, 10.5)]);
+        left.surface_velocity = 1.0;
+        right.surface_velocity = -1.0;
+        let belts = compound::Compound::new(vec![left, right]);
+        let mut sim = Simulation::new(vec![
+            body::Body::new(belts.pos, 0.0, shape::Shape::Compound(belts), true),
+            body::get_rectangle(Point::new(-5.0, 9.0), 1.0, 1.0, 1.0),
+            body::get_rectangle(Point::new(5.0, 9.0), 1.0, 1.0, 1.0)
+        ]);
+        for _ in 0..50 {
+            sim.timestep();
+        }
+        assert!((sim.bodies[1].vel - Point::new(1.0, 0.0)).norm() < 0.01);
+        assert!((sim.bodies[2].vel - Point::new(-1.0, 0.0)).norm() < 0.01);
+    }
+
+    #[test]
+    fn test_resting_depth() {
+        let mut sim = Simulation::new(vec![
+            body::get_rectangle(Point::new(0.0, 10.0), 30.0, 1.0, 0.0),
+            body::get_rectangle(Point::new(0.0, 9.0), 1.0, 1.0, 1.0),
+            body::get_segment(Point::new(3.0, 9.4), Point::new(4.0, 9.4), 1.0)
+        ]);
+        for _ in 0..300 {
+            sim.timestep();
+        }
+        // Bodies rest close to the surface of the ground instead of sinking into it.
+        assert!((sim.bodies[1].pos.y - 9.0).abs() < 2.0 * ALLOWED_PENETRATION);
+        assert!((sim.bodies[2].pos.y - 9.5).abs() < 2.0 * ALLOWED_PENETRATION);
+    }
+
+    #[test]
+    fn test_restore_snapshot() {
+        let mut sim = test_collision_3();
+        for _ in 0..100 {
+            sim.timestep();
+        }
+        let snapshot = sim.snapshot();
+        let run = |sim: &mut Simulation| {
+            let mut events = vec![];
+            for _ in 0..100 {
+                sim.timestep();
+                events.extend(sim.drain_events());
+            }
+            (scene::Scene::from_simulation(sim), sim.collision_handler.get_cache(), events)
+        };
+        let first = run(&mut sim);
+        sim.restore(&snapshot);
+        let second = run(&mut sim);
+        assert!(first == second);
+    }
+
+    #[test]
+    fn test_determinism() {
+        let mut sim1 = test_random_polygons(7, 20);
+        let mut sim2 = test_random_polygons(7, 20);
+        assert!(test_random_polygons(8, 20).get_state_hash() != sim1.get_state_hash());
+        for _ in 0..200 {
+            sim1.timestep();
+            sim2.timestep();
+            assert_eq!(sim1.get_state_hash(), sim2.get_state_hash());
+        }
+    }
+
+    #[test]
+    fn test_revolute_joints() {
+        let mut sim = test_chain(10);
+        for _ in 0..300 {
+            sim.timestep();
+        }
+        // The chain swings over to the other side without coming apart.
+        assert!(sim.bodies[10].pos.x < -5.0);
+        for joint in sim.joints.iter() {
+            let (body1, body2) = (&sim.bodies[joint.body1], &sim.bodies[joint.body2]);
+            let anchor1 = body1.pos + joint.anchor1.rotate(body1.apos);
+            let anchor2 = body2.pos + joint.anchor2.rotate(body2.apos);
+            assert!((anchor1 - anchor2).norm() < 0.05);
+        }
+        sim.remove_body(5);
+        assert_eq!(sim.joints.len(), 8);
+        assert!(sim.joints.iter().all(|joint| joint.body1 < 10 && joint.body2 < 10 && (joint.body1 != 4 || joint.body2 == 3)));
+    }
+
+    #[test]
+    fn test_fixed_point() {
+        let mut reference = boxes_on_ground::<f64>();
+        let mut sim1 = boxes_on_ground::<Fixed>();
+        let mut sim2 = boxes_on_ground::<Fixed>();
+        for _ in 0..300 {
+            reference.timestep();
+            sim1.timestep();
+            sim2.timestep();
+            assert_eq!(sim1.get_state_hash(), sim2.get_state_hash());
+        }
+        // Fixed point results are close to the f64 ones, but not the same.
+        for (body, expected) in sim1.bodies.iter().zip(reference.bodies.iter()) {
+            assert!((body.pos.to_f64() - expected.pos).norm() < 0.01);
+            assert!((body.vel.to_f64() - expected.vel).norm() < 0.01);
+        }
+    }
+}
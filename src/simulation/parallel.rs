@@ -0,0 +1,190 @@
+use std::sync::Barrier;
+use std::thread;
+
+use point::Point;
+use scalar::Scalar;
+use simulation::body::Body;
+use simulation::collisions::{Constraint, Velocities};
+use simulation::joint::JointConstraint;
+use super::NUM_ITERATIONS;
+
+// The velocities that are shared between the solver threads. Within a batch every
+// dynamic body belongs to a single constraint, so its velocity is only read and
+// written by the thread that resolves that constraint, and the velocities of
+// static bodies are never written.
+#[derive(Clone, Copy)]
+struct SharedVelocities<S> {
+    bodies: *mut Body<S>
+}
+
+unsafe impl<S: Send> Send for SharedVelocities<S> {}
+unsafe impl<S: Send> Sync for SharedVelocities<S> {}
+
+impl<S: Scalar> SharedVelocities<S> {
+    fn new(bodies: &mut [Body<S>]) -> SharedVelocities<S> {
+        SharedVelocities { bodies: bodies.as_mut_ptr() }
+    }
+}
+
+// The body indices of the constraints are in bounds of the bodies the pointer was
+// taken from, which outlive the threads. Only the velocity fields are accessed, so
+// no reference to a whole body is shared between the threads.
+impl<S: Scalar> Velocities<S> for SharedVelocities<S> {
+    fn vel_at(&self, body: usize, relative_pos: Point<S>) -> Point<S> {
+        unsafe {
+            let body = self.bodies.add(body);
+            (*body).vel + relative_pos.orth() * (*body).avel
+        }
+    }
+
+    fn add_vel(&mut self, body: usize, vel: Point<S>, avel: S) {
+        unsafe {
+            let body = self.bodies.add(body);
+            (*body).vel += vel;
+            (*body).avel += avel;
+        }
+    }
+}
+
+// Calls f on every item on up to num_threads threads, each of which handles a
+// contiguous chunk of the items. The results are in the order of the items.
+pub fn map<T, R, F>(items: &[T], num_threads: usize, f: F) -> Vec<R> where T: Sync, R: Send, F: Fn(&T) -> R + Sync {
+    if num_threads <= 1 || items.len() < 2 {
+        return items.iter().map(f).collect()
+    }
+    let chunk_size = items.len().div_ceil(num_threads);
+    let f = &f;
+    thread::scope(|scope| {
+        let handles: Vec<_> = items.chunks(chunk_size).map(|chunk| scope.spawn(move || chunk.iter().map(f).collect::<Vec<R>>())).collect();
+        handles.into_iter().flat_map(|handle| handle.join().unwrap()).collect()
+    })
+}
+
+// Colours the constraints so that no two constraints in a batch share a dynamic
+// body. Each constraint goes into the first batch it fits in, in the order of the
+// constraints, so the batches only depend on the constraints themselves.
+pub fn get_batches<S: Scalar>(constraints: &[Constraint<S>], num_bodies: usize) -> Vec<Vec<usize>> {
+    let mut batches: Vec<Vec<usize>> = vec![];
+    // The batches that contain a constraint of each body.
+    let mut body_batches: Vec<Vec<usize>> = vec![vec![]; num_bodies];
+    for (index, constraint) in constraints.iter().enumerate() {
+        let dynamic: Vec<usize> = [(constraint.body1, constraint.is_static1), (constraint.body2, constraint.is_static2)].iter()
+            .filter(|&&(_, is_static)| !is_static).map(|&(body, _)| body).collect();
+        let batch = (0..).find(|batch| dynamic.iter().all(|&body| !body_batches[body].contains(batch))).unwrap();
+        if batch == batches.len() {
+            batches.push(vec![]);
+        }
+        batches[batch].push(index);
+        for &body in dynamic.iter() {
+            body_batches[body].push(batch);
+        }
+    }
+    batches
+}
+
+// Runs the iterations of the solver with the batches of get_batches resolved one
+// after another and the constraints of a batch split between the threads. The
+// constraints of a batch are independent of each other, so the result does not
+// depend on the number of threads. The joints are resolved by the first thread
+// after the batches of each iteration.
+pub fn resolve_constraints<S: Scalar>(constraints: &mut [Constraint<S>], joints: &mut [JointConstraint<S>], bodies: &mut [Body<S>], num_threads: usize) {
+    let batches = get_batches(constraints, bodies.len());
+    if batches.is_empty() && joints.is_empty() {
+        return
+    }
+    let has_joints = !joints.is_empty();
+    let mut joints = Some(joints);
+    // Sort the constraints by batch so that every thread gets a contiguous part of
+    // each batch, and put them back into their order afterwards.
+    let order: Vec<usize> = batches.iter().flat_map(|batch| batch.iter().cloned()).collect();
+    let mut sorted: Vec<Constraint<S>> = order.iter().map(|&index| constraints[index]).collect();
+    {
+        let mut chunks: Vec<Vec<&mut [Constraint<S>]>> = (0..num_threads).map(|_| vec![]).collect();
+        let mut rest = &mut sorted[..];
+        for batch in batches.iter() {
+            let (batch_constraints, remaining) = rest.split_at_mut(batch.len());
+            rest = remaining;
+            let mut batch_chunks = batch_constraints.chunks_mut(batch.len().div_ceil(num_threads));
+            for thread_chunks in chunks.iter_mut() {
+                thread_chunks.push(batch_chunks.next().unwrap_or_default());
+            }
+        }
+        let shared = SharedVelocities::new(bodies);
+        let barrier = &Barrier::new(num_threads);
+        thread::scope(|scope| {
+            for mut thread_chunks in chunks.into_iter() {
+                let mut thread_joints = joints.take();
+                scope.spawn(move || {
+                    let mut shared = shared;
+                    for _ in 0..NUM_ITERATIONS {
+                        for chunk in thread_chunks.iter_mut() {
+                            for constraint in chunk.iter_mut() {
+                                constraint.resolve(&mut shared);
+                            }
+                            barrier.wait();
+                        }
+                        if let Some(ref mut joints) = thread_joints {
+                            for joint in joints.iter_mut() {
+                                joint.resolve(&mut shared);
+                            }
+                        }
+                        if has_joints {
+                            barrier.wait();
+                        }
+                    }
+                });
+            }
+        });
+    }
+    for (&index, constraint) in order.iter().zip(sorted) {
+        constraints[index] = constraint;
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use simulation::{Simulation, test_random_polygons, test_collision_4};
+
+    #[test]
+    fn test_parallel_narrowphase() {
+        let mut sim = test_random_polygons(3, 40);
+        for _ in 0..200 {
+            sim.timestep();
+        }
+        let serial = sim.collision_handler.find_collisions(&mut sim.bodies);
+        let collisions = sim.collision_handler.collisions.clone();
+        assert!(!collisions.is_empty());
+        sim.collision_handler.set_num_threads(4);
+        assert_eq!(sim.collision_handler.find_collisions(&mut sim.bodies), serial);
+        assert_eq!(sim.collision_handler.collisions, collisions);
+    }
+
+    #[test]
+    fn test_parallel_solver() {
+        let run = |num_threads: usize| {
+            let mut sim = test_collision_4();
+            sim.collision_handler.set_num_threads(num_threads);
+            (0..300).map(|_| { sim.timestep(); sim.get_state_hash() }).collect::<Vec<u64>>()
+        };
+        assert_eq!(run(2), run(4));
+        assert_eq!(run(3), run(3));
+        let mut sim: Simulation = test_collision_4();
+        sim.collision_handler.set_num_threads(4);
+        for _ in 0..300 {
+            sim.timestep();
+        }
+        // The boxes come to rest on the ground instead of falling through it.
+        assert!(sim.bodies.iter().filter(|body| !body.is_static).all(|body| body.pos.y < 9.5 && body.vel.norm() < 0.1));
+        let constraints = sim.collision_handler.get_constraints(&sim.bodies);
+        for batch in get_batches(&constraints, sim.bodies.len()) {
+            let mut dynamic: Vec<usize> = batch.iter()
+                .flat_map(|&index| vec![constraints[index].body1, constraints[index].body2])
+                .filter(|&body| !sim.bodies[body].is_static).collect();
+            let length = dynamic.len();
+            dynamic.sort();
+            dynamic.dedup();
+            assert_eq!(dynamic.len(), length);
+        }
+    }
+}
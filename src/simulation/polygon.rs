@@ -1,24 +1,61 @@
 use point::Point;
+use scalar::Scalar;
+use simulation::aabb::Aabb;
 
-#[derive(Debug)]
-pub struct Polygon {
-    pub pos: Point,
-    pub vertices: Vec<Point>,
-    offsets: Vec<Point>
+#[derive(Debug, Clone)]
+pub struct Polygon<S = f64> {
+    pub pos: Point<S>,
+    pub vertices: Vec<Point<S>>,
+    // The polygon is rounded by a skin of this thickness around its vertices.
+    pub radius: S,
+    // See Shape::get_surface_velocity.
+    pub surface_velocity: S,
+    offsets: Vec<Point<S>>
 }
 
-impl Polygon {
-    pub fn new(vertices: Vec<Point>) -> Polygon {
+impl<S: Scalar> Polygon<S> {
+    pub fn new(vertices: Vec<Point<S>>) -> Polygon<S> {
+        Polygon::with_radius(vertices, S::zero())
+    }
+
+    pub fn with_radius(vertices: Vec<Point<S>>, radius: S) -> Polygon<S> {
         let pos = Polygon::get_center_of_mass(&vertices);
         let offsets = vertices.iter().map(|x| (*x) - pos).collect();
         Polygon {
-            pos: pos,
-            vertices: vertices,
-            offsets: offsets
+            pos,
+            vertices,
+            radius,
+            surface_velocity: S::zero(),
+            offsets
+        }
+    }
+
+    // A polygon at the origin with vertices given relative to its center of mass.
+    pub fn from_offsets(offsets: Vec<Point<S>>, radius: S) -> Polygon<S> {
+        Polygon {
+            pos: Point::zero(),
+            vertices: offsets.clone(),
+            radius,
+            surface_velocity: S::zero(),
+            offsets
         }
     }
 
-    pub fn project(&self, normal: Point) -> [f64;2] {
+    // The convex hull of a point cloud, see convex_hull.
+    pub fn from_hull(points: &[Point<S>], tolerance: S) -> Result<Polygon<S>, String> {
+        let hull = convex_hull(points, tolerance)?;
+        if hull.len() < 3 {
+            return Err("points do not span an area".to_string())
+        }
+        Ok(Polygon::new(hull))
+    }
+
+    pub fn project(&self, normal: Point<S>) -> [S;2] {
+        let projected = self.project_core(normal);
+        [projected[0] - self.radius, projected[1] + self.radius]
+    }
+
+    pub fn project_core(&self, normal: Point<S>) -> [S;2] {
         let mut min = self.vertices[0] * normal;
         let mut max = self.vertices[0] * normal;
         for vertex in self.vertices[1..].iter() {
@@ -33,7 +70,12 @@ impl Polygon {
         [min, max]
     }
     
-    pub fn get_normals(&self) -> Vec<Point> {
+    // The vertex of the core (without radius) that is furthest in the given direction.
+    pub fn support_core(&self, direction: Point<S>) -> Point<S> {
+        support_point(&self.vertices, direction)
+    }
+
+    pub fn get_normals(&self) -> Vec<Point<S>> {
         let mut normals = vec![];
         for (x, y) in self.vertices.iter().zip(self.vertices[1..].iter().chain([self.vertices[0]].iter())) {
             normals.push((*x - *y).normalized().orth())
@@ -41,47 +83,199 @@ impl Polygon {
         normals
     }
 
-    pub fn update_pos(&mut self, pos: Point, apos: f64) {
+    pub fn update_pos(&mut self, pos: Point<S>, apos: S) {
         self.pos = pos;
         self.vertices = self.offsets.iter().map(|x| ((*x).rotate(apos)) + pos).collect();
     }
 
-    fn get_center_of_mass(vertices: &Vec<Point>) -> Point {
-        vertices.iter().fold(Point{x:0.0, y:0.0}, |acc, &x| acc + x) / (vertices.len() as f64)
+    pub fn get_aabb(&self) -> Aabb<S> {
+        Aabb::from_points(&self.vertices).expand(self.radius)
+    }
+
+    pub fn get_offsets(&self) -> &[Point<S>] {
+        &self.offsets
     }
 
-    pub fn get_moment_of_inertia(&self) -> f64 {
-        let mut inertia = 0.0;
-        for i in 1..self.offsets.len() {
+    pub fn get_area(&self) -> S {
+        signed_area(&self.vertices).abs()
+    }
+
+    // The center of the area, which differs from pos (the average of the vertices)
+    // unless the polygon is a triangle or symmetric.
+    pub fn get_centroid(&self) -> Point<S> {
+        let mut centroid = Point::zero();
+        let mut double_area = S::zero();
+        for i in 0..self.vertices.len() {
+            let v1 = self.vertices[i] - self.pos;
+            let v2 = self.vertices[(i+1) % self.vertices.len()] - self.pos;
+            let cross = v1.orth() * v2;
+            double_area += cross;
+            centroid += (v1 + v2) * cross;
+        }
+        self.pos + centroid / (double_area * S::from_f64(3.0))
+    }
+
+    fn get_center_of_mass(vertices: &[Point<S>]) -> Point<S> {
+        vertices.iter().fold(Point::zero(), |acc, &x| acc + x) / S::from_usize(vertices.len())
+    }
+
+    pub fn get_moment_of_inertia(&self) -> S {
+        // The skin of rounded polygons is not taken into account.
+        let mut inertia = S::zero();
+        for i in 0..self.offsets.len() {
             let v1 = self.offsets[i];
             let v2 = self.offsets[(i+1) % self.offsets.len()];
             inertia += (v1.orth() * v2).abs() * (v1 * v1 + v1 * v2 + v2 * v2);
         }
-        let mut norm_factor = 0.0;
-        for i in 1..self.offsets.len() {
+        let mut norm_factor = S::zero();
+        for i in 0..self.offsets.len() {
             let v1 = self.offsets[i];
             let v2 = self.offsets[(i+1) % self.offsets.len()];
             norm_factor += (v1.orth() * v2).abs();
 
         }
 
-        inertia / (6.0 * norm_factor)
+        inertia / (S::from_f64(6.0) * norm_factor)
     // }
     }
 
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Winding {
+    CounterClockwise,
+    Clockwise,
+    // The polygon has no area.
+    Degenerate
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Validation {
+    pub winding: Winding,
+    pub is_convex: bool,
+    pub is_self_intersecting: bool
+}
+
+impl Validation {
+    // Whether the vertices can be used for a Polygon directly.
+    pub fn is_valid_convex(&self) -> bool {
+        self.winding != Winding::Degenerate && self.is_convex && !self.is_self_intersecting
+    }
+}
+
+// Positive for counterclockwise vertices (in a coordinate system with the y axis pointing up).
+pub fn signed_area<S: Scalar>(vertices: &[Point<S>]) -> S {
+    let mut area = S::zero();
+    for i in 0..vertices.len() {
+        area += vertices[i].orth() * vertices[(i + 1) % vertices.len()];
+    }
+    S::from_f64(0.5) * area
+}
+
+pub fn validate<S: Scalar>(vertices: &[Point<S>]) -> Validation {
+    let area = signed_area(vertices);
+    let winding = if area > S::zero() {
+        Winding::CounterClockwise
+    }
+    else if area < S::zero() {
+        Winding::Clockwise
+    }
+    else {
+        Winding::Degenerate
+    };
+    let num_vertices = vertices.len();
+    let turns: Vec<S> = (0..num_vertices).map(|i| {
+        let previous = vertices[(i + num_vertices - 1) % num_vertices];
+        let next = vertices[(i + 1) % num_vertices];
+        (vertices[i] - previous).orth() * (next - vertices[i])
+    }).collect();
+    let is_self_intersecting = (0..num_vertices).any(|i| {
+        (i + 1..num_vertices).any(|j| {
+            // Neighbouring edges share a vertex and only intersect if they overlap.
+            let is_neighbour = j == i + 1 || (i == 0 && j == num_vertices - 1);
+            !is_neighbour && segments_intersect(vertices[i], vertices[(i + 1) % num_vertices], vertices[j], vertices[(j + 1) % num_vertices])
+        })
+    });
+    // All turns have the same direction. Self intersecting polygons such as
+    // pentagrams can also fulfill this, so they are never convex.
+    let is_convex = !is_self_intersecting && winding != Winding::Degenerate && turns.iter().all(|&turn| turn * area >= S::zero());
+    Validation { winding, is_convex, is_self_intersecting }
+}
+
+pub fn segments_intersect<S: Scalar>(start1: Point<S>, end1: Point<S>, start2: Point<S>, end2: Point<S>) -> bool {
+    let zero = S::zero();
+    let side = |a: Point<S>, b: Point<S>, c: Point<S>| (b - a).orth() * (c - a);
+    let on_segment = |a: Point<S>, b: Point<S>, c: Point<S>| c.x >= a.x.min(b.x) && c.x <= a.x.max(b.x) && c.y >= a.y.min(b.y) && c.y <= a.y.max(b.y);
+    let d1 = side(start2, end2, start1);
+    let d2 = side(start2, end2, end1);
+    let d3 = side(start1, end1, start2);
+    let d4 = side(start1, end1, end2);
+    if ((d1 > zero && d2 < zero) || (d1 < zero && d2 > zero)) && ((d3 > zero && d4 < zero) || (d3 < zero && d4 > zero)) {
+        return true
+    }
+    (d1 == zero && on_segment(start2, end2, start1)) || (d2 == zero && on_segment(start2, end2, end1)) ||
+        (d3 == zero && on_segment(start1, end1, start2)) || (d4 == zero && on_segment(start1, end1, end2))
+}
+
+pub fn convex_hull<S: Scalar>(points: &[Point<S>], tolerance: S) -> Result<Vec<Point<S>>, String> {
+    // Andrew's monotone chain. Returns the counterclockwise hull without
+    // collinear vertices or vertices that are closer than tolerance.
+    if points.iter().any(|point| point.x.partial_cmp(&point.x).is_none() || point.y.partial_cmp(&point.y).is_none()) {
+        return Err("points are not numbers".to_string())
+    }
+    let mut sorted = points.to_vec();
+    sorted.sort_by(|a, b| (a.x, a.y).partial_cmp(&(b.x, b.y)).unwrap());
+    if sorted.len() < 3 {
+        return Ok(sorted)
+    }
+    let half_hull = |points: &mut dyn Iterator<Item=&Point<S>>| {
+        let mut hull: Vec<Point<S>> = vec![];
+        for &point in points {
+            if hull.last().is_some_and(|&last| (point - last).norm() < tolerance) {
+                continue
+            }
+            // Remove the last vertex unless it is a left turn that is clearly off the line.
+            while hull.len() >= 2 {
+                let origin = hull[hull.len() - 2];
+                let last = hull[hull.len() - 1];
+                if (point - origin).orth() * (origin - last) > tolerance * (point - origin).norm() {
+                    break
+                }
+                hull.pop();
+            }
+            hull.push(point);
+        }
+        hull
+    };
+    let mut lower = half_hull(&mut sorted.iter());
+    let mut upper = half_hull(&mut sorted.iter().rev());
+    lower.pop();
+    upper.pop();
+    lower.append(&mut upper);
+    if lower.len() == 2 && (lower[0] - lower[1]).norm() < tolerance {
+        lower.pop();
+    }
+    Ok(lower)
+}
+
+pub fn support_point<S: Scalar>(points: &[Point<S>], direction: Point<S>) -> Point<S> {
+    let mut best = points[0];
+    for &point in points[1..].iter() {
+        if point * direction > best * direction {
+            best = point;
+        }
+    }
+    best
+}
+
 #[cfg(test)]
 mod tests{
     use super::*;
     use std::f64;
+    use simulation::DISTANCE_TOLERANCE;
     #[test] 
     fn test_projection() {
-        let mut vertices = vec![];
-        vertices.push(Point::new(0.0, 0.0));
-        vertices.push(Point::new(1.0, 0.0));
-        vertices.push(Point::new(1.0, 1.0));
-        vertices.push(Point::new(0.0, 1.0));
+        let vertices = vec![Point::new(0.0, 0.0), Point::new(1.0, 0.0), Point::new(1.0, 1.0), Point::new(0.0, 1.0)];
         let poly = Polygon::new(vertices);
         let projection = poly.project(Point::new(1.0, 0.0));
         assert_eq!(projection[0], 0.0);
@@ -90,22 +284,14 @@ mod tests{
 
     #[test]
     fn test_moment_of_inertia_square() {
-        let mut vertices = vec![];
-        vertices.push(Point::new(0.0, 0.0));
-        vertices.push(Point::new(1.0, 0.0));
-        vertices.push(Point::new(1.0, 1.0));
-        vertices.push(Point::new(0.0, 1.0));
+        let vertices = vec![Point::new(0.0, 0.0), Point::new(1.0, 0.0), Point::new(1.0, 1.0), Point::new(0.0, 1.0)];
         let poly = Polygon::new(vertices);
         assert!((poly.get_moment_of_inertia() - 1.0/6.0).abs() < 0.00001);
     }
 
     #[test]
     fn test_moment_of_inertia_square_shifted() {
-        let mut vertices = vec![];
-        vertices.push(Point::new(1.0, 0.0));
-        vertices.push(Point::new(2.0, 0.0));
-        vertices.push(Point::new(2.0, 1.0));
-        vertices.push(Point::new(1.0, 1.0));
+        let vertices = vec![Point::new(1.0, 0.0), Point::new(2.0, 0.0), Point::new(2.0, 1.0), Point::new(1.0, 1.0)];
         let poly = Polygon::new(vertices);
         assert!((poly.get_moment_of_inertia() - 1.0/6.0).abs() < 0.00001);
     }
@@ -123,4 +309,42 @@ mod tests{
         let poly = Polygon::new(points);
         assert!((poly.get_moment_of_inertia() - 1.0/2.0).abs() < 0.0001);
     }
+
+    #[test]
+    fn test_validate() {
+        let square = vec![Point::new(0.0, 0.0), Point::new(1.0, 0.0), Point::new(1.0, 1.0), Point::new(0.0, 1.0)];
+        assert_eq!(validate(&square), Validation { winding: Winding::CounterClockwise, is_convex: true, is_self_intersecting: false });
+        let reversed: Vec<Point> = square.iter().rev().cloned().collect();
+        assert_eq!(validate(&reversed).winding, Winding::Clockwise);
+        assert!(validate(&reversed).is_convex);
+        let arrow = vec![Point::new(0.0, 0.0), Point::new(1.0, 0.5), Point::new(0.0, 1.0), Point::new(0.5, 0.5)];
+        assert!(!validate(&arrow).is_convex);
+        assert!(!validate(&arrow).is_self_intersecting);
+        let bowtie = vec![Point::new(0.0, 0.0), Point::new(1.0, 1.0), Point::new(1.0, 0.0), Point::new(0.0, 1.0)];
+        assert!(validate(&bowtie).is_self_intersecting);
+        assert!(!validate(&bowtie).is_valid_convex());
+    }
+
+    #[test]
+    fn test_convex_hull() {
+        let points = vec![
+            Point::new(1.0, 1.0), Point::new(0.0, 0.0), Point::new(0.5, 0.5), Point::new(2.0, 0.0),
+            Point::new(1.0, 0.0), Point::new(2.0, 2.0), Point::new(0.0, 2.0), Point::new(2.0, 2.0000000001),
+            Point::new(0.0, 1.0)
+        ];
+        let tolerance = DISTANCE_TOLERANCE;
+        let hull = convex_hull(&points, tolerance).unwrap();
+        let expected = [Point::new(0.0, 0.0), Point::new(2.0, 0.0), Point::new(2.0, 2.0), Point::new(0.0, 2.0)];
+        assert_eq!(hull.len(), expected.len());
+        for (&vertex, &expected_vertex) in hull.iter().zip(expected.iter()) {
+            assert!((vertex - expected_vertex).norm() < 0.00001);
+        }
+        assert_eq!(validate(&hull).winding, Winding::CounterClockwise);
+        let polygon = Polygon::from_hull(&points, tolerance).unwrap();
+        assert!((polygon.get_area() - 4.0).abs() < 0.00001);
+        let line = [Point::new(0.0, 0.0), Point::new(1.0, 1.0), Point::new(2.0, 2.0)];
+        assert!(convex_hull(&line, tolerance).unwrap().len() == 2);
+        assert!(Polygon::from_hull(&line, tolerance).is_err());
+        assert!(convex_hull(&[Point::new(0.0, 0.0), Point::new(f64::NAN, 1.0), Point::new(2.0, 0.0)], tolerance).is_err());
+    }
 }
@@ -0,0 +1,239 @@
+use std::fs::File;
+use std::io;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+
+use serde::{Serialize, Deserialize};
+use serde_json;
+
+use point::Point;
+use simulation::Simulation;
+use simulation::scene::{Scene, BodyDescription};
+
+// A recorded run: the scene it started from and, for every step, the inputs that
+// were applied before the step and the state hash after it. Playing it back
+// re-simulates the scene and checks each hash, which reproduces a run exactly.
+// The recording has to start from a simulation that was just loaded or created,
+// since the state that scenes do not store (such as bodies passing through one way
+// platforms) is not part of it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Replay {
+    pub scene: Scene,
+    pub steps: Vec<Step>
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Step {
+    #[serde(default)]
+    pub inputs: Vec<Input>,
+    pub hash: u64
+}
+
+// Points are in world coordinates, except for the anchor of the mouse joint.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Input {
+    // Acts at the center of the body if no point is given.
+    Force {
+        body: usize,
+        force: Point,
+        #[serde(default)]
+        point: Option<Point>
+    },
+    Impulse {
+        body: usize,
+        impulse: Point,
+        #[serde(default)]
+        point: Option<Point>
+    },
+    Spawn { body: BodyDescription },
+    Remove { body: usize },
+    MouseJoint { body: usize, anchor: Point, target: Point }
+}
+
+pub struct Recorder {
+    scene: Scene,
+    steps: Vec<Step>,
+    inputs: Vec<Input>
+}
+
+pub struct Player {
+    pub sim: Simulation,
+    steps: Vec<Step>,
+    step: usize
+}
+
+impl Input {
+    pub fn apply(&self, sim: &mut Simulation) -> Result<(), String> {
+        let check = |sim: &Simulation, body: usize| {
+            if body < sim.bodies.len() { Ok(()) } else { Err(format!("there is no body {}", body)) }
+        };
+        match *self {
+            Input::Force { body, force, point } => {
+                check(sim, body)?;
+                let body = &mut sim.bodies[body];
+                match point {
+                    Some(point) => { body.apply_force_at(force, point - body.pos) }
+                    None => { body.apply_force(force) }
+                }
+            }
+            Input::Impulse { body, impulse, point } => {
+                check(sim, body)?;
+                let body = &mut sim.bodies[body];
+                match point {
+                    Some(point) => { body.apply_impulse_at(impulse, point - body.pos) }
+                    None => { body.apply_impulse(impulse) }
+                }
+            }
+            Input::Spawn { ref body } => {
+                sim.bodies.push(body.to_body()?);
+            }
+            Input::Remove { body } => {
+                check(sim, body)?;
+                sim.remove_body(body);
+            }
+            Input::MouseJoint { body, anchor, target } => {
+                check(sim, body)?;
+                sim.drag_body(body, anchor, target);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Recorder {
+    pub fn new(sim: &Simulation) -> Recorder {
+        Recorder {
+            scene: Scene::from_simulation(sim),
+            steps: vec![],
+            inputs: vec![]
+        }
+    }
+
+    // Applies the input right away and records it for the next step.
+    pub fn apply(&mut self, sim: &mut Simulation, input: Input) -> Result<(), String> {
+        input.apply(sim)?;
+        self.inputs.push(input);
+        Ok(())
+    }
+
+    pub fn timestep(&mut self, sim: &mut Simulation) {
+        sim.timestep();
+        self.steps.push(Step { inputs: self.inputs.split_off(0), hash: sim.get_state_hash() });
+    }
+
+    pub fn num_steps(&self) -> usize {
+        self.steps.len()
+    }
+
+    // Inputs that were applied after the last step are not part of the replay.
+    pub fn finish(self) -> Replay {
+        Replay { scene: self.scene, steps: self.steps }
+    }
+}
+
+impl Player {
+    pub fn new(replay: Replay) -> io::Result<Player> {
+        Ok(Player {
+            sim: replay.scene.to_simulation()?,
+            steps: replay.steps,
+            step: 0
+        })
+    }
+
+    // The number of steps that were played so far.
+    pub fn get_step(&self) -> usize {
+        self.step
+    }
+
+    pub fn num_steps(&self) -> usize {
+        self.steps.len()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.step >= self.steps.len()
+    }
+
+    // Applies the inputs of the next step, steps the simulation and compares the
+    // state hash with the recorded one. Does nothing once the replay is finished.
+    pub fn timestep(&mut self) -> Result<(), String> {
+        let step = match self.steps.get(self.step) {
+            Some(step) => { step }
+            None => { return Ok(()) }
+        };
+        self.step += 1;
+        for input in step.inputs.iter() {
+            input.apply(&mut self.sim).map_err(|message| format!("step {}: invalid input: {}", self.step, message))?;
+        }
+        self.sim.timestep();
+        let hash = self.sim.get_state_hash();
+        if hash != step.hash {
+            return Err(format!("step {}: replay diverged, expected state hash {:016x}, got {:016x}", self.step, step.hash, hash))
+        }
+        Ok(())
+    }
+}
+
+impl Replay {
+    pub fn from_json(json: &str) -> io::Result<Replay> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Replay> {
+        Ok(serde_json::from_reader(BufReader::new(File::open(path)?))?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer(&mut writer, self)?;
+        writeln!(writer)?;
+        writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use simulation::body;
+    use simulation::test_collision_4;
+
+    fn record() -> Replay {
+        let mut sim = test_collision_4();
+        let mut recorder = Recorder::new(&sim);
+        for step in 0..200 {
+            let inputs = match step {
+                10 => { vec![Input::Impulse { body: 0, impulse: Point::new(3.0, 0.0), point: Some(sim.bodies[0].pos + Point::new(0.0, 0.5)) }] }
+                20 => { vec![Input::Spawn { body: BodyDescription::from_body(&body::get_circle(Point::new(-2.0, 0.0), 1.0, 0.4)) }] }
+                30 => { vec![Input::Remove { body: 3 }] }
+                40..=80 => { vec![Input::MouseJoint { body: 5, anchor: Point::new(0.5, 0.5), target: Point::new(2.0, -3.0) }] }
+                90 => { vec![Input::Force { body: 7, force: Point::new(0.0, -500.0), point: None }] }
+                _ => { vec![] }
+            };
+            for input in inputs {
+                recorder.apply(&mut sim, input).unwrap();
+            }
+            recorder.timestep(&mut sim);
+        }
+        recorder.finish()
+    }
+
+    #[test]
+    fn test_replay() {
+        let replay = Replay::from_json(&record().to_json()).unwrap();
+        assert_eq!(replay.steps.len(), 200);
+        let mut player = Player::new(replay.clone()).unwrap();
+        while !player.is_finished() {
+            player.timestep().unwrap();
+        }
+        assert_eq!(player.get_step(), 200);
+        let mut tampered = replay;
+        tampered.steps[40].inputs[0] = Input::MouseJoint { body: 5, anchor: Point::new(0.5, 0.5), target: Point::new(2.0, -3.1) };
+        let mut player = Player::new(tampered).unwrap();
+        let error = (0..200).map(|_| player.timestep()).find(|result| result.is_err()).unwrap().unwrap_err();
+        assert!(error.starts_with("step 41:"), "{}", error);
+    }
+}
@@ -0,0 +1,513 @@
+use std::fs::File;
+use std::io;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+
+use serde::{Serialize, Deserialize};
+use serde_json;
+
+use point::Point;
+use simulation::Simulation;
+use simulation::body::Body;
+use simulation::shape::Shape;
+use simulation::circle::Circle;
+use simulation::polygon;
+use simulation::polygon::Polygon;
+use simulation::segment::Segment;
+use simulation::capsule::Capsule;
+use simulation::chain;
+use simulation::chain::Chain;
+use simulation::compound::Compound;
+use simulation::grid::{Cells, Grid};
+use simulation::filter::CollisionFilter;
+use simulation::joint::RevoluteJoint;
+use simulation::force_field::ForceField;
+use super::{GRAVITY, GRAVITY_DIR, FRICTION, RESTITUTION};
+
+// A JSON description of a simulation. Shapes are given relative to the position of
+// their body (its center of mass) at an angle of zero, which is how the shapes store
+// them, so a scene written from a simulation loads back into identical bodies.
+// Joints refer to bodies by their index.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Scene {
+    #[serde(default)]
+    pub world: World,
+    pub bodies: Vec<BodyDescription>,
+    #[serde(default)]
+    pub joints: Vec<RevoluteJoint>
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct World {
+    pub gravity: Point,
+    #[serde(default)]
+    pub force_fields: Vec<ForceField>
+}
+
+// Missing fields take the values that Body::new uses. A missing inertia is
+// computed from the shape and the mass.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BodyDescription {
+    pub shape: ShapeDescription,
+    pub pos: Point,
+    #[serde(default)]
+    pub apos: f64,
+    #[serde(default = "origin")]
+    pub vel: Point,
+    #[serde(default)]
+    pub avel: f64,
+    pub mass: f64,
+    #[serde(default)]
+    pub inertia: Option<f64>,
+    #[serde(default)]
+    pub is_static: bool,
+    #[serde(default)]
+    pub is_sensor: bool,
+    #[serde(default = "one")]
+    pub gravity_scale: f64,
+    #[serde(default)]
+    pub filter: CollisionFilter,
+    #[serde(default)]
+    pub one_way_direction: Option<Point>,
+    // The surface velocity of the shape. The pieces of a compound have their own.
+    #[serde(default)]
+    pub surface_velocity: f64,
+    #[serde(default)]
+    pub breaking_impulse: Option<f64>,
+    #[serde(default = "friction")]
+    pub friction: f64,
+    #[serde(default = "restitution")]
+    pub restitution: f64
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ShapeDescription {
+    Circle { radius: f64 },
+    // Convex and counterclockwise.
+    Polygon {
+        vertices: Vec<Point>,
+        #[serde(default)]
+        radius: f64
+    },
+    Segment { start: Point, end: Point },
+    Capsule { start: Point, end: Point, radius: f64 },
+    Chain { vertices: Vec<Point>, closed: bool },
+    Compound { pieces: Vec<PieceDescription> },
+    // The center of mass is relative to the top left corner of the cells. If the
+    // pieces are missing they are built from the cells.
+    Grid {
+        width: usize,
+        height: usize,
+        cell_size: f64,
+        densities: Vec<f64>,
+        #[serde(default)]
+        center: Option<Point>,
+        #[serde(default)]
+        pieces: Option<Vec<PieceDescription>>
+    }
+}
+
+// A convex piece of a compound shape. The vertices are relative to the center of
+// the piece, which is at offset from the center of the compound.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PieceDescription {
+    pub offset: Point,
+    pub vertices: Vec<Point>,
+    #[serde(default)]
+    pub radius: f64,
+    #[serde(default)]
+    pub surface_velocity: f64
+}
+
+impl Default for World {
+    fn default() -> World {
+        World { gravity: GRAVITY * GRAVITY_DIR, force_fields: vec![] }
+    }
+}
+
+impl Scene {
+    pub fn from_simulation(sim: &Simulation) -> Scene {
+        Scene {
+            world: World { gravity: sim.gravity, force_fields: sim.force_fields.clone() },
+            bodies: sim.bodies.iter().map(describe_body).collect(),
+            joints: sim.joints.clone()
+        }
+    }
+
+    pub fn to_simulation(&self) -> io::Result<Simulation> {
+        for (i, field) in self.world.force_fields.iter().enumerate() {
+            check_force_field(field).map_err(|message| invalid_data(&format!("force field {}: {}", i, message)))?;
+        }
+        let bodies = self.bodies.iter().enumerate().map(|(i, description)| {
+            build_body(description).map_err(|message| invalid_data(&format!("body {}: {}", i, message)))
+        }).collect::<io::Result<Vec<Body>>>()?;
+        for (i, joint) in self.joints.iter().enumerate() {
+            if joint.body1 >= bodies.len() || joint.body2 >= bodies.len() || joint.body1 == joint.body2 {
+                return Err(invalid_data(&format!("joint {}: needs two different bodies of the scene", i)))
+            }
+        }
+        let mut sim = Simulation::new(bodies);
+        sim.gravity = self.world.gravity;
+        sim.force_fields = self.world.force_fields.clone();
+        sim.joints = self.joints.clone();
+        Ok(sim)
+    }
+
+    pub fn from_json(json: &str) -> io::Result<Scene> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Scene> {
+        Ok(serde_json::from_reader(BufReader::new(File::open(path)?))?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        writeln!(writer)?;
+        writer.flush()
+    }
+}
+
+impl BodyDescription {
+    pub fn from_body(body: &Body) -> BodyDescription {
+        describe_body(body)
+    }
+
+    pub fn to_body(&self) -> Result<Body, String> {
+        build_body(self)
+    }
+}
+
+fn describe_body(body: &Body) -> BodyDescription {
+    BodyDescription {
+        shape: describe_shape(&body.shape),
+        pos: body.pos,
+        apos: body.apos,
+        vel: body.vel,
+        avel: body.avel,
+        mass: body.mass,
+        inertia: Some(body.inertia),
+        is_static: body.is_static,
+        is_sensor: body.is_sensor,
+        gravity_scale: body.gravity_scale,
+        filter: body.filter,
+        one_way_direction: body.one_way_direction,
+        surface_velocity: body.shape.get_surface_velocity(),
+        breaking_impulse: body.breaking_impulse,
+        friction: body.friction,
+        restitution: body.restitution
+    }
+}
+
+fn describe_shape(shape: &Shape) -> ShapeDescription {
+    match *shape {
+        Shape::Circle(ref circle) => { ShapeDescription::Circle { radius: circle.radius } }
+        Shape::Polygon(ref polygon) => {
+            ShapeDescription::Polygon { vertices: counterclockwise(polygon.get_offsets()), radius: polygon.radius }
+        }
+        Shape::Segment(ref segment) => {
+            let offsets = segment.get_offsets();
+            ShapeDescription::Segment { start: offsets[0], end: offsets[1] }
+        }
+        Shape::Capsule(ref capsule) => {
+            let offsets = capsule.segment.get_offsets();
+            ShapeDescription::Capsule { start: offsets[0], end: offsets[1], radius: capsule.radius }
+        }
+        Shape::Chain(ref chain) => {
+            ShapeDescription::Chain { vertices: chain.get_offsets().to_vec(), closed: chain.closed }
+        }
+        Shape::Compound(ref compound) => { ShapeDescription::Compound { pieces: describe_pieces(compound) } }
+        Shape::Grid(ref grid) => {
+            ShapeDescription::Grid {
+                width: grid.cells.width,
+                height: grid.cells.height,
+                cell_size: grid.cells.cell_size,
+                densities: grid.cells.densities.clone(),
+                center: Some(grid.center),
+                pieces: Some(describe_pieces(&grid.compound))
+            }
+        }
+    }
+}
+
+fn describe_pieces(compound: &Compound) -> Vec<PieceDescription> {
+    compound.children.iter().zip(compound.get_offsets().iter()).map(|(child, &offset)| {
+        match *child {
+            Shape::Polygon(ref polygon) => {
+                PieceDescription { offset, vertices: counterclockwise(polygon.get_offsets()), radius: polygon.radius, surface_velocity: polygon.surface_velocity }
+            }
+            _ => { panic!("describe_pieces - compound child is not a polygon") }
+        }
+    }).collect()
+}
+
+fn build_body(description: &BodyDescription) -> Result<Body, String> {
+    let shape = build_shape(&description.shape)?;
+    if !description.is_static && shape.is_static_only() {
+        return Err("chains can only be used for static bodies".to_string())
+    }
+    // Written so that NaN fails as well.
+    let has_positive_mass = description.mass > 0.0 && description.mass.is_finite();
+    if !description.is_static && !has_positive_mass {
+        return Err("dynamic bodies need a finite positive mass".to_string())
+    }
+    if !(description.friction >= 0.0 && description.friction.is_finite()) {
+        return Err("friction needs to be finite and not negative".to_string())
+    }
+    if !(description.restitution >= 0.0 && description.restitution <= 1.0) {
+        return Err("restitution needs to be between 0 and 1".to_string())
+    }
+    let mut body = Body::new(Point::new(0.0, 0.0), description.mass, shape, description.is_static);
+    body.pos = description.pos;
+    body.apos = description.apos;
+    body.vel = description.vel;
+    body.avel = description.avel;
+    if let Some(inertia) = description.inertia {
+        body.inertia = inertia;
+    }
+    body.is_sensor = description.is_sensor;
+    body.gravity_scale = description.gravity_scale;
+    body.filter = description.filter;
+    body.one_way_direction = description.one_way_direction;
+    if !matches!(body.shape, Shape::Compound(_)) {
+        body.shape.set_surface_velocity(description.surface_velocity);
+    }
+    body.breaking_impulse = description.breaking_impulse;
+    body.friction = description.friction;
+    body.restitution = description.restitution;
+    body.shape.update_pos(body.pos, body.apos);
+    Ok(body)
+}
+
+// Builds the shape at the origin.
+fn build_shape(description: &ShapeDescription) -> Result<Shape, String> {
+    match *description {
+        ShapeDescription::Circle { radius } => {
+            check_radius(radius)?;
+            Ok(Shape::Circle(Circle::new(Point::new(0.0, 0.0), radius)))
+        }
+        ShapeDescription::Polygon { ref vertices, radius } => {
+            Ok(Shape::Polygon(build_polygon(vertices, radius)?))
+        }
+        ShapeDescription::Segment { start, end } => { Ok(Shape::Segment(Segment::from_offsets(start, end))) }
+        ShapeDescription::Capsule { start, end, radius } => {
+            check_radius(radius)?;
+            Ok(Shape::Capsule(Capsule { segment: Segment::from_offsets(start, end), radius }))
+        }
+        ShapeDescription::Chain { ref vertices, closed } => {
+            chain::check_vertices(vertices, closed)?;
+            Ok(Shape::Chain(Chain::from_offsets(vertices.clone(), closed)))
+        }
+        ShapeDescription::Compound { ref pieces } => {
+            if pieces.is_empty() {
+                return Err("compounds need at least one piece".to_string())
+            }
+            Ok(Shape::Compound(build_compound(pieces)?))
+        }
+        ShapeDescription::Grid { width, height, cell_size, ref densities, center, ref pieces } => {
+            let cells = Cells::from_densities(width, height, cell_size, densities.clone())?;
+            match (center, pieces) {
+                (Some(center), Some(pieces)) => {
+                    Ok(Shape::Grid(Grid::from_parts(cells, center, build_compound(pieces)?)?))
+                }
+                _ => {
+                    // Place the center of mass at the origin.
+                    let origin = Point::new(0.0, 0.0) - cells.get_center_of_mass();
+                    Ok(Shape::Grid(Grid::new(cells, origin)?))
+                }
+            }
+        }
+    }
+}
+
+fn build_polygon(vertices: &[Point], radius: f64) -> Result<Polygon, String> {
+    if vertices.len() < 3 || !polygon::validate(vertices).is_valid_convex() {
+        return Err("polygons need at least three vertices in convex order".to_string())
+    }
+    if !(radius >= 0.0 && radius.is_finite()) {
+        return Err("polygon radii need to be finite and not negative".to_string())
+    }
+    Ok(Polygon::from_offsets(counterclockwise(vertices), radius))
+}
+
+fn check_radius(radius: f64) -> Result<(), String> {
+    if !(radius > 0.0 && radius.is_finite()) {
+        return Err("radii need to be finite and positive".to_string())
+    }
+    Ok(())
+}
+
+fn check_force_field(field: &ForceField) -> Result<(), String> {
+    match *field {
+        ForceField::Uniform { acceleration } => {
+            if !(acceleration.x.is_finite() && acceleration.y.is_finite()) {
+                return Err("the acceleration needs to be finite".to_string())
+            }
+        }
+        ForceField::Radial { center, strength, radius } => {
+            if !(center.x.is_finite() && center.y.is_finite() && strength.is_finite()) {
+                return Err("the center and strength need to be finite".to_string())
+            }
+            check_radius(radius)?;
+        }
+    }
+    Ok(())
+}
+
+// Polygons are counterclockwise in the simulation, so clockwise vertices are
+// reversed both when writing and when loading a scene.
+fn counterclockwise(vertices: &[Point]) -> Vec<Point> {
+    let mut vertices = vertices.to_vec();
+    if polygon::validate(&vertices).winding == polygon::Winding::Clockwise {
+        vertices.reverse();
+    }
+    vertices
+}
+
+fn build_compound(pieces: &[PieceDescription]) -> Result<Compound, String> {
+    let children = pieces.iter().map(|piece| {
+        let mut polygon = build_polygon(&piece.vertices, piece.radius)?;
+        polygon.surface_velocity = piece.surface_velocity;
+        Ok(polygon)
+    }).collect::<Result<Vec<Polygon>, String>>()?;
+    Ok(Compound::from_offsets(children, pieces.iter().map(|piece| piece.offset).collect()))
+}
+
+fn origin() -> Point {
+    Point::new(0.0, 0.0)
+}
+
+fn one() -> f64 {
+    1.0
+}
+
+fn friction() -> f64 {
+    FRICTION
+}
+
+fn restitution() -> f64 {
+    RESTITUTION
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use simulation::body;
+
+    #[test]
+    fn test_round_trip() {
+        // A scene written from a simulation loads back into a simulation that
+        // writes the same scene and keeps doing so while both are stepped.
+        let mut cells = Cells::new(3, 2, 0.5);
+        for &(x, y) in [(0, 0), (1, 0), (2, 0), (0, 1)].iter() {
+            cells.set(x, y, 1.0);
+        }
+        let mut bodies = vec![
+            body::get_circle(Point::new(-3.0, 0.0), 1.0, 0.5),
+            body::get_regular_polygon(Point::new(-1.5, 0.0), 0.7, 5, 1.0),
+            body::get_rounded_polygon(vec![Point::new(0.0, 0.0), Point::new(1.0, 0.0), Point::new(0.5, 1.0)], 0.1, 1.0),
+            body::get_capsule(Point::new(1.5, 0.0), Point::new(2.5, 0.3), 0.2, 1.0),
+            body::get_segment(Point::new(3.0, 0.0), Point::new(4.0, -0.5), 1.0),
+            body::get_concave_polygon(vec![Point::new(-2.0, 2.0), Point::new(0.0, 2.0), Point::new(0.0, 3.0), Point::new(-1.0, 2.5), Point::new(-2.0, 3.0)], 2.0).unwrap(),
+            body::get_grid(Point::new(1.0, 2.0), cells, false).unwrap(),
+            body::get_chain(vec![Point::new(-6.0, 0.0), Point::new(-5.0, 6.0), Point::new(6.0, 6.0), Point::new(7.0, 0.0)], false).unwrap(),
+        ];
+        bodies[1].avel = 2.0;
+        bodies[2].breaking_impulse = Some(100.0);
+        bodies[3].filter.group_index = -1;
+        bodies[4].shape.set_surface_velocity(0.5);
+        if let Shape::Compound(ref mut compound) = bodies[5].shape {
+            compound.children[0].set_surface_velocity(-1.0);
+        }
+        bodies[6].shape.set_surface_velocity(2.0);
+        bodies[0].restitution = 0.5;
+        bodies[1].friction = 0.2;
+        let mut sim = Simulation::new(bodies);
+        sim.gravity = Point::new(0.5, 9.0);
+        sim.force_fields = vec![
+            ForceField::Uniform { acceleration: Point::new(-1.0, 0.0) },
+            ForceField::Radial { center: Point::new(0.0, 3.0), strength: 4.0, radius: 2.0 }
+        ];
+        for _ in 0..20 {
+            sim.timestep();
+        }
+        let scene = Scene::from_simulation(&sim);
+        let json = scene.to_json();
+        let mut loaded = Scene::from_json(&json).unwrap().to_simulation().unwrap();
+        assert_eq!(Scene::from_simulation(&loaded).to_json(), json);
+        for _ in 0..50 {
+            sim.timestep();
+            loaded.timestep();
+        }
+        assert_eq!(Scene::from_simulation(&loaded), Scene::from_simulation(&sim));
+    }
+
+    #[test]
+    fn test_defaults() {
+        let json = r#"{"bodies": [
+            {"shape": {"type": "circle", "radius": 1.0}, "pos": {"x": 1.0, "y": 2.0}, "mass": 2.0},
+            {"shape": {"type": "polygon", "vertices": [{"x": -1.0, "y": -1.0}, {"x": 1.0, "y": -1.0}, {"x": 1.0, "y": 1.0}, {"x": -1.0, "y": 1.0}]},
+             "pos": {"x": 0.0, "y": 5.0}, "mass": 0.0, "is_static": true}
+        ]}"#;
+        let sim = Scene::from_json(json).unwrap().to_simulation().unwrap();
+        assert_eq!(sim.bodies.len(), 2);
+        assert_eq!(sim.gravity, GRAVITY * GRAVITY_DIR);
+        assert_eq!(sim.bodies[0].inertia, 1.0);
+        assert_eq!(sim.bodies[0].gravity_scale, 1.0);
+        assert!(sim.bodies[1].is_static);
+        assert_eq!(sim.bodies[1].shape.get_aabb().min, Point::new(-1.0, 4.0));
+        let twisted = json.replace("\"x\": 1.0, \"y\": -1.0}, {\"x\": 1.0, \"y\": 1.0}", "\"x\": 1.0, \"y\": 1.0}, {\"x\": 1.0, \"y\": -1.0}");
+        assert!(Scene::from_json(&twisted).unwrap().to_simulation().is_err());
+    }
+
+    #[test]
+    fn test_invalid_values() {
+        let circle = r#"{"shape": {"type": "circle", "radius": 1.0}, "pos": {"x": 0.0, "y": 0.0}, "mass": 1.0}"#;
+        let load = |world: &str, body: &str| Scene::from_json(&format!(r#"{{"world": {}, "bodies": [{}]}}"#, world, body)).unwrap().to_simulation();
+        let world = r#"{"gravity": {"x": 0.0, "y": 10.0}}"#;
+        assert!(load(world, circle).is_ok());
+        assert!(load(world, &circle.replace("\"radius\": 1.0", "\"radius\": -1.0")).is_err());
+        assert!(load(world, &circle.replace("\"mass\": 1.0", "\"mass\": 0.0")).is_err());
+        assert!(load(world, &circle.replace("\"mass\": 1.0", "\"mass\": 1.0, \"restitution\": 2.0")).is_err());
+        assert!(load(world, &circle.replace("\"mass\": 1.0", "\"mass\": 1.0, \"friction\": -1.0")).is_err());
+        let capsule = r#"{"shape": {"type": "capsule", "start": {"x": 0.0, "y": 0.0}, "end": {"x": 1.0, "y": 0.0}, "radius": 0.0}, "pos": {"x": 0.0, "y": 0.0}, "mass": 1.0}"#;
+        assert!(load(world, capsule).is_err());
+        let field = r#"{"gravity": {"x": 0.0, "y": 10.0}, "force_fields": [{"type": "radial", "center": {"x": 0.0, "y": 0.0}, "strength": 1.0, "radius": 0.0}]}"#;
+        assert!(load(field, circle).is_err());
+        assert_eq!(load(&field.replace("\"radius\": 0.0", "\"radius\": 3.0"), circle).unwrap().force_fields.len(), 1);
+    }
+
+    #[test]
+    fn test_clockwise_polygon() {
+        let vertices = vec![Point::new(0.0, 0.0), Point::new(0.0, 1.0), Point::new(2.0, 1.0), Point::new(2.0, 0.0)];
+        assert_eq!(polygon::validate(&vertices).winding, polygon::Winding::Clockwise);
+        let sim = Simulation::new(vec![body::get_polygon(vertices, 1.0)]);
+        let json = Scene::from_simulation(&sim).to_json();
+        let loaded = Scene::from_json(&json).unwrap().to_simulation().unwrap();
+        assert_eq!(Scene::from_simulation(&loaded).to_json(), json);
+        match loaded.bodies[0].shape {
+            Shape::Polygon(ref polygon) => {
+                assert_eq!(polygon::validate(&polygon.vertices).winding, polygon::Winding::CounterClockwise);
+                assert_eq!(loaded.bodies[0].shape.get_aabb().min, sim.bodies[0].shape.get_aabb().min);
+                assert_eq!(loaded.bodies[0].shape.get_aabb().max, sim.bodies[0].shape.get_aabb().max);
+            }
+            _ => { panic!("test_clockwise_polygon - not a polygon") }
+        }
+        // Clockwise vertices written by hand load the same way.
+        let mut scene = Scene::from_json(&json).unwrap();
+        if let ShapeDescription::Polygon { ref mut vertices, .. } = scene.bodies[0].shape {
+            vertices.reverse();
+        }
+        assert_eq!(Scene::from_simulation(&scene.to_simulation().unwrap()).to_json(), Scene::from_simulation(&loaded).to_json());
+    }
+}
@@ -0,0 +1,66 @@
+use point::Point;
+use scalar::Scalar;
+use simulation::aabb::Aabb;
+
+#[derive(Debug, Clone)]
+pub struct Segment<S = f64> {
+    pub pos: Point<S>,
+    pub start: Point<S>,
+    pub end: Point<S>,
+    // See Shape::get_surface_velocity.
+    pub surface_velocity: S,
+    offsets: [Point<S>; 2]
+}
+
+impl<S: Scalar> Segment<S> {
+    pub fn new(start: Point<S>, end: Point<S>) -> Segment<S> {
+        let pos = start.middle(end);
+        Segment {
+            pos,
+            start,
+            end,
+            surface_velocity: S::zero(),
+            offsets: [start - pos, end - pos]
+        }
+    }
+
+    // A segment at the origin with its ends given relative to its center.
+    pub fn from_offsets(start: Point<S>, end: Point<S>) -> Segment<S> {
+        Segment {
+            pos: Point::zero(),
+            start,
+            end,
+            surface_velocity: S::zero(),
+            offsets: [start, end]
+        }
+    }
+
+    pub fn get_offsets(&self) -> [Point<S>; 2] {
+        self.offsets
+    }
+
+    pub fn project(&self, normal: Point<S>) -> [S;2] {
+        let projected_start = self.start * normal;
+        let projected_end = self.end * normal;
+        [projected_start.min(projected_end), projected_start.max(projected_end)]
+    }
+
+    pub fn update_pos(&mut self, pos: Point<S>, apos: S) {
+        self.pos = pos;
+        self.start = self.offsets[0].rotate(apos) + pos;
+        self.end = self.offsets[1].rotate(apos) + pos;
+    }
+
+    pub fn length(&self) -> S {
+        (self.end - self.start).norm()
+    }
+
+    pub fn get_aabb(&self) -> Aabb<S> {
+        Aabb::from_points(&[self.start, self.end])
+    }
+
+    pub fn get_moment_of_inertia(&self) -> S {
+        // Thin rod rotating around its center
+        self.length().powi(2) / S::from_f64(12.0)
+    }
+}
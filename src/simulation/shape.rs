@@ -1,38 +1,162 @@
 use point::Point;
-use simulation::polygon::Polygon;
+use scalar::Scalar;
+use simulation::aabb::Aabb;
+use simulation::polygon::{Polygon, support_point};
 use simulation::circle::Circle;
+use simulation::segment::Segment;
+use simulation::capsule::Capsule;
+use simulation::chain::Chain;
+use simulation::compound::Compound;
+use simulation::grid::Grid;
 
-trait Project {
-    fn project(&self, normal: Point) -> [f64;2];
+pub trait Project<S: Scalar> {
+    fn project(&self, normal: Point<S>) -> [S;2];
 }
 
-pub enum Shape {
-    Circle(Circle),
-    Polygon(Polygon)
+#[derive(Debug, Clone)]
+pub enum Shape<S = f64> {
+    Circle(Circle<S>),
+    Polygon(Polygon<S>),
+    Segment(Segment<S>),
+    Capsule(Capsule<S>),
+    Chain(Chain<S>),
+    Compound(Compound<S>),
+    Grid(Grid<S>)
 }
 
-impl Shape {
-    pub fn update_pos(&mut self, pos: Point, apos: f64) {
+impl<S: Scalar> Shape<S> {
+    pub fn update_pos(&mut self, pos: Point<S>, apos: S) {
         match *self {
             Shape::Circle(ref mut circle) => { circle.pos = pos }
             Shape::Polygon(ref mut polygon) => { polygon.update_pos(pos, apos) }
+            Shape::Segment(ref mut segment) => { segment.update_pos(pos, apos) }
+            Shape::Capsule(ref mut capsule) => { capsule.update_pos(pos, apos) }
+            Shape::Chain(ref mut chain) => { chain.update_pos(pos, apos) }
+            Shape::Compound(ref mut compound) => { compound.update_pos(pos, apos) }
+            Shape::Grid(ref mut grid) => { grid.update_pos(pos, apos) }
         }
     }
 
-    pub fn get_moment_of_inertia(&self) -> f64 {
+    pub fn get_moment_of_inertia(&self) -> S {
         match *self {
             Shape::Circle(ref circle) => { circle.get_moment_of_inertia() }
             Shape::Polygon(ref polygon) => { polygon.get_moment_of_inertia() }
+            Shape::Segment(ref segment) => { segment.get_moment_of_inertia() }
+            Shape::Capsule(ref capsule) => { capsule.get_moment_of_inertia() }
+            Shape::Chain(ref chain) => { chain.get_moment_of_inertia() }
+            Shape::Compound(ref compound) => { compound.get_moment_of_inertia() }
+            Shape::Grid(ref grid) => { grid.get_moment_of_inertia() }
         }
     }
+
+    pub fn get_aabb(&self) -> Aabb<S> {
+        match *self {
+            Shape::Circle(ref circle) => { circle.get_aabb() }
+            Shape::Polygon(ref polygon) => { polygon.get_aabb() }
+            Shape::Segment(ref segment) => { segment.get_aabb() }
+            Shape::Capsule(ref capsule) => { capsule.get_aabb() }
+            Shape::Chain(ref chain) => { chain.get_aabb() }
+            Shape::Compound(ref compound) => { compound.get_aabb() }
+            Shape::Grid(ref grid) => { grid.get_aabb() }
+        }
+    }
+
+    // The tangential speed of the surface of a convex shape or a grid, which moves
+    // touching bodies through friction like a conveyor belt. Positive values move
+    // the surface clockwise on screen. Each child of a compound has its own, so
+    // compounds report zero.
+    pub fn get_surface_velocity(&self) -> S {
+        match *self {
+            Shape::Circle(ref circle) => { circle.surface_velocity }
+            Shape::Polygon(ref polygon) => { polygon.surface_velocity }
+            Shape::Segment(ref segment) => { segment.surface_velocity }
+            Shape::Capsule(ref capsule) => { capsule.segment.surface_velocity }
+            Shape::Chain(ref chain) => { chain.surface_velocity }
+            Shape::Compound(_) => { S::zero() }
+            Shape::Grid(ref grid) => { grid.surface_velocity }
+        }
+    }
+
+    // Sets the surface velocity of the shape, or of all children of a compound or grid.
+    pub fn set_surface_velocity(&mut self, surface_velocity: S) {
+        match *self {
+            Shape::Circle(ref mut circle) => { circle.surface_velocity = surface_velocity }
+            Shape::Polygon(ref mut polygon) => { polygon.surface_velocity = surface_velocity }
+            Shape::Segment(ref mut segment) => { segment.surface_velocity = surface_velocity }
+            Shape::Capsule(ref mut capsule) => { capsule.segment.surface_velocity = surface_velocity }
+            Shape::Chain(ref mut chain) => { chain.surface_velocity = surface_velocity }
+            Shape::Compound(ref mut compound) => {
+                for child in compound.children.iter_mut() {
+                    child.set_surface_velocity(surface_velocity);
+                }
+            }
+            Shape::Grid(ref mut grid) => {
+                grid.surface_velocity = surface_velocity;
+                for child in grid.compound.children.iter_mut() {
+                    child.set_surface_velocity(surface_velocity);
+                }
+            }
+        }
+    }
+
+    pub fn is_static_only(&self) -> bool {
+        matches!(*self, Shape::Chain(_))
+    }
+
+    // Circles, segments and capsules are all a segment (possibly of length zero)
+    // with a radius (possibly zero) around it.
+    pub fn as_rounded_segment(&self) -> Option<(Point<S>, Point<S>, S)> {
+        match *self {
+            Shape::Circle(ref circle) => { Some((circle.pos, circle.pos, circle.radius)) }
+            Shape::Segment(ref segment) => { Some((segment.start, segment.end, S::zero())) }
+            Shape::Capsule(ref capsule) => { Some((capsule.segment.start, capsule.segment.end, capsule.radius)) }
+            Shape::Polygon(_) | Shape::Chain(_) | Shape::Compound(_) | Shape::Grid(_) => { None }
+        }
+    }
+
+    // Every shape is a convex core with a radius around it.
+    pub fn get_radius(&self) -> S {
+        match *self {
+            Shape::Polygon(ref polygon) => { polygon.radius }
+            Shape::Chain(_) | Shape::Compound(_) | Shape::Grid(_) => { S::zero() }
+            ref shape => { shape.as_rounded_segment().unwrap().2 }
+        }
+    }
+
+    // The point of the core that is furthest in the given direction. For chains
+    // and compound shapes this is the support point of their convex hull.
+    pub fn support_core(&self, direction: Point<S>) -> Point<S> {
+        match *self {
+            Shape::Polygon(ref polygon) => { polygon.support_core(direction) }
+            Shape::Chain(ref chain) => { support_point(&chain.vertices, direction) }
+            Shape::Compound(ref compound) => { compound.support(direction) }
+            Shape::Grid(ref grid) => { grid.compound.support(direction) }
+            ref shape => {
+                let (start, end, _) = shape.as_rounded_segment().unwrap();
+                support_point(&[start, end], direction)
+            }
+        }
+    }
+
+    pub fn support(&self, direction: Point<S>) -> Point<S> {
+        // A zero direction has no side to extend the radius to.
+        if direction * direction == S::zero() {
+            return self.support_core(direction)
+        }
+        self.support_core(direction) + direction.normalized() * self.get_radius()
+    }
 }
 
-impl Project for Shape {
-    fn project(&self, normal: Point) -> [f64;2] {
+impl<S: Scalar> Project<S> for Shape<S> {
+    fn project(&self, normal: Point<S>) -> [S;2] {
         match *self {
             Shape::Circle(ref circle) => { circle.project(normal) }
             Shape::Polygon(ref polygon) => { polygon.project(normal) }
+            Shape::Segment(ref segment) => { segment.project(normal) }
+            Shape::Capsule(ref capsule) => { capsule.project(normal) }
+            Shape::Chain(ref chain) => { chain.project(normal) }
+            Shape::Compound(ref compound) => { compound.project(normal) }
+            Shape::Grid(ref grid) => { grid.project(normal) }
         }
     }
 }
-